@@ -0,0 +1,25 @@
+use alloc::vec::Vec;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FormatError {
+    #[error("parsing error due to invalid iro flags {0}")]
+    InvalidIroFlags(i32),
+    #[error("parsing error due to invalid iro version {0}")]
+    InvalidIroVersion(i32),
+    #[error("failed to parse binary data")]
+    CannotParseBinary(nom::Err<::nom::error::Error<Vec<u8>>>),
+    #[error("parsing error due to invalid file flags {0}")]
+    InvalidFileFlags(i32),
+    #[error("parsing error due to invalid entry length {0}")]
+    InvalidEntryLength(u16),
+    #[error("offset {0} exceeds what iro version {1:#x} can encode")]
+    OffsetOverflow(u64, i32),
+}
+
+impl From<nom::Err<nom::error::Error<&[u8]>>> for FormatError {
+    fn from(err: nom::Err<nom::error::Error<&[u8]>>) -> Self {
+        Self::CannotParseBinary(err.map_input(|input| input.into()))
+    }
+}
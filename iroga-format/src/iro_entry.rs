@@ -0,0 +1,171 @@
+use alloc::vec::Vec;
+use core::fmt::Display;
+
+use crate::error::FormatError;
+use crate::iro_header::IroVersion;
+
+/// Length of an entry in the index section without its file path, in the latest version
+pub const INDEX_FIXED_BYTE_SIZE: usize = 20;
+
+/// Entry of the index section.
+///
+/// With the `serde` feature, the UTF-16 path is (de)serialized as a string with `/` as separator
+/// and the data length as `stored_size`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IroEntry {
+    #[cfg_attr(feature = "serde", serde(with = "serde_path"))]
+    pub path: Vec<u8>,
+    pub flags: FileFlags,
+    pub offset: u64,
+    #[cfg_attr(feature = "serde", serde(rename = "stored_size"))]
+    pub data_len: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FileFlags {
+    #[cfg_attr(feature = "serde", serde(rename = "uncompressed"))]
+    Uncompressed = 0,
+    #[cfg_attr(feature = "serde", serde(rename = "lzss"))]
+    LzssCompressed = 1,
+    #[cfg_attr(feature = "serde", serde(rename = "lzma"))]
+    LzmaCompressed = 2,
+}
+
+impl IroEntry {
+    pub fn new(path: Vec<u8>, flags: FileFlags, offset: u64, data_len: u32) -> Self {
+        IroEntry {
+            path,
+            flags,
+            offset,
+            data_len,
+        }
+    }
+
+    /// Serialize the entry in the index section layout of the given version.
+    ///
+    /// Fails when the offset does not fit in the 4 bytes of version 0x10000.
+    pub fn to_bytes(&self, version: IroVersion) -> Result<Vec<u8>, FormatError> {
+        let entry_len = self.path.len() + version.entry_fixed_len();
+        let mut bytes = Vec::with_capacity(entry_len);
+        bytes.extend((entry_len as u16).to_le_bytes());
+        bytes.extend((self.path.len() as u16).to_le_bytes());
+        bytes.extend(&self.path);
+        bytes.extend((self.flags as i32).to_le_bytes());
+        if version == IroVersion::Zero {
+            let offset = u32::try_from(self.offset)
+                .map_err(|_| FormatError::OffsetOverflow(self.offset, version as i32))?;
+            bytes.extend(offset.to_le_bytes());
+        } else {
+            bytes.extend(self.offset.to_le_bytes());
+        }
+        bytes.extend(self.data_len.to_le_bytes());
+        Ok(bytes)
+    }
+}
+
+/// Entry borrowing its path from the index bytes it was parsed from
+#[derive(Clone, Copy, Debug)]
+pub struct IroEntryRef<'a> {
+    pub path: &'a [u8],
+    pub flags: FileFlags,
+    pub offset: u64,
+    pub data_len: u32,
+}
+
+impl IroEntryRef<'_> {
+    /// Whether the entry has the given path, compared case-insensitively and accepting both `/`
+    /// and `\\` as separator, without allocating
+    pub fn path_eq(&self, path: &str) -> bool {
+        let entry_chars = char::decode_utf16(
+            self.path
+                .chunks_exact(2)
+                .map(|ch| u16::from_le_bytes([ch[0], ch[1]])),
+        );
+        let mut path_chars = path.chars();
+        for entry_char in entry_chars {
+            let (Ok(entry_char), Some(path_char)) = (entry_char, path_chars.next()) else {
+                return false;
+            };
+            let entry_char = if entry_char == '\\' { '/' } else { entry_char };
+            let path_char = if path_char == '\\' { '/' } else { path_char };
+            if !entry_char.to_lowercase().eq(path_char.to_lowercase()) {
+                return false;
+            }
+        }
+        self.path.len().is_multiple_of(2) && path_chars.next().is_none()
+    }
+}
+
+impl From<IroEntryRef<'_>> for IroEntry {
+    fn from(value: IroEntryRef<'_>) -> Self {
+        IroEntry::new(
+            value.path.to_vec(),
+            value.flags,
+            value.offset,
+            value.data_len,
+        )
+    }
+}
+
+/// Serialize the entry in the index section layout of the latest version
+impl From<IroEntry> for Vec<u8> {
+    fn from(value: IroEntry) -> Self {
+        value
+            .to_bytes(IroVersion::Two)
+            .expect("any offset fits in the latest version")
+    }
+}
+
+impl TryFrom<i32> for FileFlags {
+    type Error = FormatError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(FileFlags::Uncompressed),
+            1 => Ok(FileFlags::LzssCompressed),
+            2 => Ok(FileFlags::LzmaCompressed),
+            _ => Err(FormatError::InvalidFileFlags(value)),
+        }
+    }
+}
+
+impl Display for FileFlags {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FileFlags::Uncompressed => f.pad("uncompressed"),
+            FileFlags::LzssCompressed => f.pad("lzss"),
+            FileFlags::LzmaCompressed => f.pad("lzma"),
+        }
+    }
+}
+
+/// UTF-16 paths as strings with `/` as separator, the same shown by the command line
+#[cfg(feature = "serde")]
+mod serde_path {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    use serde::{Deserialize, Deserializer, Serializer, ser::Error};
+
+    pub fn serialize<S: Serializer>(path: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let path = char::decode_utf16(
+            path.chunks_exact(2)
+                .map(|ch| u16::from_le_bytes([ch[0], ch[1]])),
+        )
+        .map(|ch| ch.map(|ch| if ch == '\\' { '/' } else { ch }))
+        .collect::<Result<String, _>>()
+        .map_err(S::Error::custom)?;
+        serializer.serialize_str(&path)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let path = String::deserialize(deserializer)?;
+        Ok(path
+            .replace('/', "\\")
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect())
+    }
+}
@@ -0,0 +1,114 @@
+use alloc::vec::Vec;
+use core::fmt::Display;
+
+use crate::error::FormatError;
+
+pub const IRO_SIG: i32 = 0x534f5249; // represents IROS text
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IroHeader {
+    pub version: IroVersion,
+    pub flags: IroFlags,
+    pub size: i32,
+    pub num_files: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[allow(dead_code)]
+pub enum IroFlags {
+    #[cfg_attr(feature = "serde", serde(rename = "full"))]
+    None = 0,
+    #[cfg_attr(feature = "serde", serde(rename = "patch"))]
+    Patch = 1,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[allow(dead_code)]
+pub enum IroVersion {
+    #[cfg_attr(feature = "serde", serde(rename = "0x10000"))]
+    Zero = 0x10000,
+    #[cfg_attr(feature = "serde", serde(rename = "0x10001"))]
+    One = 0x10001,
+    #[default]
+    #[cfg_attr(feature = "serde", serde(rename = "0x10002"))]
+    Two = 0x10002,
+}
+
+impl IroHeader {
+    pub fn new(version: IroVersion, flags: IroFlags, size: i32, num_files: u32) -> Self {
+        IroHeader {
+            version,
+            flags,
+            size,
+            num_files,
+        }
+    }
+}
+
+impl IroVersion {
+    /// Length of an entry in the index section without its file path: the offset takes 4 bytes
+    /// in version 0x10000 and 8 bytes since version 0x10001
+    pub fn entry_fixed_len(&self) -> usize {
+        if *self == IroVersion::Zero { 16 } else { 20 }
+    }
+}
+
+impl From<IroHeader> for Vec<u8> {
+    fn from(value: IroHeader) -> Self {
+        [
+            IRO_SIG.to_le_bytes(),
+            (value.version as i32).to_le_bytes(),
+            (value.flags as i32).to_le_bytes(),
+            value.size.to_le_bytes(),
+            value.num_files.to_le_bytes(),
+        ]
+        .concat()
+    }
+}
+
+impl TryFrom<i32> for IroFlags {
+    type Error = FormatError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(IroFlags::None),
+            1 => Ok(IroFlags::Patch),
+            _ => Err(FormatError::InvalidIroFlags(value)),
+        }
+    }
+}
+
+impl Display for IroFlags {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            IroFlags::None => f.pad("Full IRO"),
+            IroFlags::Patch => f.pad("Patch IRO"),
+        }
+    }
+}
+
+impl Display for IroVersion {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            IroVersion::Zero => f.pad("0x10000"),
+            IroVersion::One => f.pad("0x10001"),
+            IroVersion::Two => f.pad("0x10002"),
+        }
+    }
+}
+
+impl TryFrom<i32> for IroVersion {
+    type Error = FormatError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0x10000 => Ok(IroVersion::Zero),
+            0x10001 => Ok(IroVersion::One),
+            0x10002 => Ok(IroVersion::Two),
+            _ => Err(FormatError::InvalidIroVersion(value)),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use nom::{
+    bytes::{complete::{tag, take}, streaming},
+    number::complete::{le_i32, le_u16, le_u32, le_u64},
+};
+
+use crate::{
+    error::FormatError,
+    iro_entry::{FileFlags, IroEntryRef},
+    iro_header::{IroFlags, IroHeader, IroVersion, IRO_SIG},
+};
+
+pub fn parse_iro_header_v2(bytes: &[u8]) -> Result<(&[u8], IroHeader), FormatError> {
+    let (bytes, _) = tag(&IRO_SIG.to_le_bytes()[..])(bytes)?;
+    let (bytes, version) = le_i32(bytes)?;
+    let (bytes, flags) = le_i32(bytes)?;
+    let (bytes, _) = tag(&16i32.to_le_bytes()[..])(bytes)?;
+    let (bytes, num_files) = le_u32(bytes)?;
+
+    Ok((
+        bytes,
+        IroHeader::new(IroVersion::try_from(version)?, IroFlags::try_from(flags)?, 16, num_files),
+    ))
+}
+
+/// Minimum length of an entry in the index section, i.e. with an empty file path
+pub fn min_entry_len(header: &IroHeader) -> usize {
+    header.version.entry_fixed_len()
+}
+
+/// Parse IroEntry including the length of entire block.
+///
+/// Returns an incomplete error when the bytes end before the block, so more bytes can be read.
+pub fn parse_iro_entry<'a>(header: &IroHeader, bytes: &'a [u8]) -> Result<(&'a [u8], IroEntryRef<'a>), FormatError> {
+    let (bytes, entry_len) = nom::number::streaming::le_u16(bytes)?;
+    if (entry_len as usize) < min_entry_len(header) {
+        return Err(FormatError::InvalidEntryLength(entry_len));
+    }
+    let (bytes, entry_bytes) = streaming::take(entry_len - 2)(bytes)?;
+    let (_, iro_entry) = parse_iro_entry_v2(header, entry_bytes)?;
+    Ok((bytes, iro_entry))
+}
+
+/// Parse IroEntryRef without considering length of entire block, borrowing the path
+pub fn parse_iro_entry_v2<'a>(header: &IroHeader, bytes: &'a [u8]) -> Result<(&'a [u8], IroEntryRef<'a>), FormatError> {
+    let (bytes, filepath_len) = le_u16(bytes)?;
+    let (bytes, filepath) = take(filepath_len)(bytes)?;
+    let (bytes, file_flags) = le_i32(bytes)?;
+    let (bytes, offset) = if header.version == IroVersion::Zero {
+        let (bytes, offset) = le_u32(bytes)?;
+        (bytes, offset as u64)
+    } else {
+        le_u64(bytes)?
+    };
+    let (bytes, data_len) = le_u32(bytes)?;
+
+    Ok((
+        bytes,
+        IroEntryRef {
+            path: filepath,
+            flags: FileFlags::try_from(file_flags)?,
+            offset,
+            data_len,
+        },
+    ))
+}
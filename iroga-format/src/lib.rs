@@ -0,0 +1,13 @@
+//! Types, parsers and serializers of the IRO archive format, without any I/O.
+//!
+//! The crate is `no_std` and only needs `alloc`, file access and compression are left to `iroga`.
+#![no_std]
+
+extern crate alloc;
+
+pub mod error;
+pub mod iro_entry;
+pub mod iro_header;
+pub mod iro_parser;
+
+pub use error::FormatError;
@@ -0,0 +1,85 @@
+use iroga_format::{
+    FormatError,
+    iro_entry::{FileFlags, IroEntry},
+    iro_header::{IroFlags, IroHeader, IroVersion},
+    iro_parser::{parse_iro_entry, parse_iro_header_v2},
+};
+
+#[test]
+pub fn header_roundtrip() {
+    let header_bytes = Vec::from(IroHeader::new(IroVersion::Two, IroFlags::Patch, 16, 3));
+
+    let (bytes, header) = parse_iro_header_v2(&header_bytes).unwrap();
+    assert!(bytes.is_empty());
+    assert_eq!(header.version, IroVersion::Two);
+    assert_eq!(header.flags, IroFlags::Patch);
+    assert_eq!(header.num_files, 3);
+}
+
+#[test]
+pub fn entry_roundtrip() {
+    let header = IroHeader::new(IroVersion::Two, IroFlags::None, 16, 1);
+    let path: Vec<u8> = "a.txt"
+        .encode_utf16()
+        .flat_map(|ch| ch.to_le_bytes())
+        .collect();
+    let entry_bytes = Vec::from(IroEntry::new(
+        path.clone(),
+        FileFlags::LzssCompressed,
+        50,
+        7,
+    ));
+
+    let (bytes, entry) = parse_iro_entry(&header, &entry_bytes).unwrap();
+    assert!(bytes.is_empty());
+    assert!(entry.path_eq("A.TXT"));
+    assert_eq!(entry.path, path.as_slice());
+    assert_eq!(entry.flags, FileFlags::LzssCompressed);
+    assert_eq!(entry.offset, 50);
+    assert_eq!(entry.data_len, 7);
+}
+
+#[test]
+pub fn entry_invalid_length() {
+    let header = IroHeader::new(IroVersion::Two, IroFlags::None, 16, 1);
+
+    let result = parse_iro_entry(&header, &[0x01, 0x00, 0x00, 0x00]);
+    assert!(matches!(result, Err(FormatError::InvalidEntryLength(1))));
+}
+
+#[test]
+pub fn entry_offset_overflow_version_zero() {
+    let entry = IroEntry::new(Vec::new(), FileFlags::Uncompressed, 1 << 32, 7);
+
+    assert_eq!(entry.to_bytes(IroVersion::One).unwrap().len(), 20);
+    let result = entry.to_bytes(IroVersion::Zero);
+    assert!(matches!(
+        result,
+        Err(FormatError::OffsetOverflow(0x1_0000_0000, 0x10000))
+    ));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+pub fn entry_serde_roundtrip() {
+    let path: Vec<u8> = "field\\md1stin"
+        .encode_utf16()
+        .flat_map(|ch| ch.to_le_bytes())
+        .collect();
+    let entry = IroEntry::new(path.clone(), FileFlags::LzmaCompressed, 50, 7);
+
+    let json = serde_json::to_string(&entry).unwrap();
+    assert_eq!(
+        json,
+        r#"{"path":"field/md1stin","flags":"lzma","offset":50,"stored_size":7}"#
+    );
+    let entry: IroEntry = serde_json::from_str(&json).unwrap();
+    assert_eq!(entry.path, path);
+    assert_eq!(entry.flags, FileFlags::LzmaCompressed);
+
+    let header = IroHeader::new(IroVersion::Zero, IroFlags::Patch, 16, 1);
+    assert_eq!(
+        serde_json::to_string(&header).unwrap(),
+        r#"{"version":"0x10000","flags":"patch","size":16,"num_files":1}"#
+    );
+}
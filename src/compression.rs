@@ -1,14 +1,397 @@
-use lzma_rs::error::Error as LzmaError;
+use std::{
+    fmt::Display,
+    io::{BufReader, Read, Write},
+    str::FromStr,
+};
+
+use lzma_rust2::{Lzma2Options, Lzma2Reader, Lzma2Writer, LzmaOptions, LzmaReader, LzmaWriter};
 use lzs::{Lzs, LzsError};
+use iroga_format::iro_entry::FileFlags;
 use nom::number::complete::le_i32;
 
 use crate::Error;
 
-pub fn lzss_decompress<R: std::io::Read, W: std::io::Write>(
+/// Size in bytes of the LZMA properties (lc/lp/pb byte followed by the dictionary size)
+const LZMA_PROPS_SIZE: i32 = 5;
+
+/// Size in bytes of the LZMA2 properties, chunks follow the sizes directly
+const LZMA2_PROPS_SIZE: i32 = 0;
+
+/// Ring buffer size of the LZSS window
+const LZSS_N: usize = 1 << 12;
+
+/// Maximum match length of LZSS
+const LZSS_F: usize = 18;
+
+/// Minimum match length of LZSS minus one
+const LZSS_THRESHOLD: usize = 2;
+
+/// Compression applied to the files when packing an IRO archive
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Compression {
+    #[default]
+    None,
+    Lzss,
+    Lzma(LzmaPreset),
+    Lzma2(LzmaPreset),
+    /// Keep the smallest between uncompressed, LZSS and LZMA
+    Auto(LzmaPreset),
+}
+
+/// Settings of the LZMA encoder
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LzmaPreset {
+    /// Compression level from 0 (fastest) to 9 (smallest)
+    pub level: u32,
+    /// Dictionary size in bytes (default is the one of the compression level)
+    pub dict_size: Option<u32>,
+}
+
+/// Compression to apply to the files matching a glob
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompressionRule {
+    pub glob: String,
+    pub compression: Compression,
+}
+
+impl Compression {
+    /// Replace the LZMA encoder settings, other compressions are left untouched
+    pub fn with_lzma_preset(self, preset: LzmaPreset) -> Self {
+        match self {
+            Compression::Lzma(_) => Compression::Lzma(preset),
+            Compression::Lzma2(_) => Compression::Lzma2(preset),
+            Compression::Auto(_) => Compression::Auto(preset),
+            _ => self,
+        }
+    }
+}
+
+impl LzmaPreset {
+    pub const DEFAULT_LEVEL: u32 = 6;
+
+    pub fn new(level: u32, dict_size: Option<u32>) -> Self {
+        LzmaPreset { level, dict_size }
+    }
+
+    fn lzma_options(&self) -> LzmaOptions {
+        let mut options = LzmaOptions::with_preset(self.level);
+        if let Some(dict_size) = self.dict_size {
+            options.dict_size =
+                dict_size.clamp(lzma_rust2::DICT_SIZE_MIN, lzma_rust2::DICT_SIZE_MAX);
+        }
+        options
+    }
+}
+
+impl Default for LzmaPreset {
+    fn default() -> Self {
+        LzmaPreset::new(Self::DEFAULT_LEVEL, None)
+    }
+}
+
+impl CompressionRule {
+    pub fn new(glob: impl Into<String>, compression: Compression) -> Self {
+        CompressionRule {
+            glob: glob.into(),
+            compression,
+        }
+    }
+
+    /// Match the glob against the relative file path, or only against the file name when the glob
+    /// has no path separator (e.g. `*.ogg` matches `music/intro.ogg`)
+    pub fn matches(&self, relative_path: &str) -> bool {
+        if fast_glob::glob_match(&self.glob, relative_path) {
+            return true;
+        }
+        if self.glob.contains(['/', '\\']) {
+            return false;
+        }
+        relative_path
+            .rsplit(['/', '\\'])
+            .next()
+            .is_some_and(|file_name| fast_glob::glob_match(&self.glob, file_name))
+    }
+
+    pub fn with_lzma_preset(self, preset: LzmaPreset) -> Self {
+        CompressionRule {
+            compression: self.compression.with_lzma_preset(preset),
+            ..self
+        }
+    }
+}
+
+impl FromStr for CompressionRule {
+    type Err = Error;
+
+    /// Parse a rule in the form `<glob>=<compression>` (e.g. `*.p=lzss`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (glob, compression) = s
+            .rsplit_once('=')
+            .filter(|(glob, _)| !glob.is_empty())
+            .ok_or(Error::InvalidCompressionRule(s.to_owned()))?;
+        Ok(CompressionRule::new(glob, compression.parse()?))
+    }
+}
+
+impl FromStr for Compression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "lzss" => Ok(Compression::Lzss),
+            "lzma" => Ok(Compression::Lzma(LzmaPreset::default())),
+            "lzma2" => Ok(Compression::Lzma2(LzmaPreset::default())),
+            "auto" => Ok(Compression::Auto(LzmaPreset::default())),
+            _ => Err(Error::InvalidCompression(s.to_owned())),
+        }
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Compression::None => f.write_str("none"),
+            Compression::Lzss => f.write_str("lzss"),
+            Compression::Lzma(_) => f.write_str("lzma"),
+            Compression::Lzma2(_) => f.write_str("lzma2"),
+            Compression::Auto(_) => f.write_str("auto"),
+        }
+    }
+}
+
+/// Compress all the bytes of the reader into the writer with the given compression, returning the
+/// file flags of the written data
+pub fn compress<R: std::io::Read, W: std::io::Write>(
+    compression: Compression,
+    mut reader: R,
+    mut writer: W,
+) -> Result<FileFlags, Error> {
+    match compression {
+        Compression::None => {
+            std::io::copy(&mut reader, &mut writer)?;
+            Ok(FileFlags::Uncompressed)
+        }
+        Compression::Lzss => {
+            lzss_compress(reader, writer)?;
+            Ok(FileFlags::LzssCompressed)
+        }
+        Compression::Lzma(preset) => {
+            lzma_compress(reader, writer, &preset)?;
+            Ok(FileFlags::LzmaCompressed)
+        }
+        Compression::Lzma2(preset) => {
+            lzma2_compress(reader, writer, &preset)?;
+            Ok(FileFlags::LzmaCompressed)
+        }
+        Compression::Auto(preset) => auto_compress(reader, writer, &preset),
+    }
+}
+
+/// Compress with both LZSS and LZMA and write the smallest result, the data is written
+/// uncompressed when no compression reduces its size
+pub fn auto_compress<R: std::io::Read, W: std::io::Write>(
     mut reader: R,
     mut writer: W,
+    preset: &LzmaPreset,
+) -> Result<FileFlags, Error> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    let mut smallest: Option<(FileFlags, Vec<u8>)> = None;
+    for compression in [Compression::Lzss, Compression::Lzma(*preset)] {
+        let mut compressed = Vec::new();
+        let file_flags = compress(compression, data.as_slice(), &mut compressed)?;
+        let smallest_len = smallest
+            .as_ref()
+            .map_or(data.len(), |(_, bytes)| bytes.len());
+        if compressed.len() < smallest_len {
+            smallest = Some((file_flags, compressed));
+        }
+    }
+
+    match smallest {
+        Some((file_flags, compressed)) => {
+            writer.write_all(&compressed)?;
+            Ok(file_flags)
+        }
+        None => {
+            writer.write_all(&data)?;
+            Ok(FileFlags::Uncompressed)
+        }
+    }
+}
+
+/// Decompress all the bytes of the reader into the writer according to the file flags
+pub fn decompress<R: std::io::Read, W: std::io::Write>(
+    file_flags: FileFlags,
+    reader: R,
+    mut writer: W,
 ) -> Result<(), Error> {
-    match Lzs::new(0x00).decompress(
+    std::io::copy(&mut Decompressor::new(file_flags, reader)?, &mut writer)?;
+    Ok(())
+}
+
+/// Reader decompressing the data of an entry lazily, while it is being read
+pub enum Decompressor<R> {
+    Uncompressed(R),
+    Lzss(LzssReader<BufReader<R>>),
+    Lzma(Box<LzmaReader<BufReader<R>>>),
+    Lzma2(Box<Lzma2Reader<BufReader<R>>>),
+}
+
+impl<R: Read> Decompressor<R> {
+    /// Create the decompressor of the given file flags, reading the LZMA framing if any
+    pub fn new(file_flags: FileFlags, reader: R) -> Result<Self, Error> {
+        match file_flags {
+            FileFlags::Uncompressed => Ok(Decompressor::Uncompressed(reader)),
+            FileFlags::LzssCompressed => {
+                Ok(Decompressor::Lzss(LzssReader::new(BufReader::new(reader))))
+            }
+            FileFlags::LzmaCompressed => {
+                let mut reader = BufReader::new(reader);
+                let mut header_bytes = [0u8; 8];
+                reader.read_exact(&mut header_bytes)?;
+                let bytes: &[u8] = &header_bytes;
+                let (bytes, dec_size) = le_i32(bytes)?;
+                let (_, prop_size) = le_i32(bytes)?;
+                // the size must be known, both to stop the LZMA stream and to size the dictionary
+                let dec_size = u32::try_from(dec_size)
+                    .map_err(|_| Error::InvalidDecompressedSize(dec_size))?;
+                if prop_size < LZMA_PROPS_SIZE {
+                    // the dictionary never needs to be larger than the decompressed data
+                    Ok(Decompressor::Lzma2(Box::new(Lzma2Reader::new(
+                        reader, dec_size, None,
+                    ))))
+                } else {
+                    let mut props = [0u8; LZMA_PROPS_SIZE as usize];
+                    reader.read_exact(&mut props)?;
+                    let dict_size = u32::from_le_bytes([props[1], props[2], props[3], props[4]]);
+                    let lzma_reader = LzmaReader::new_with_props(
+                        reader,
+                        dec_size as u64,
+                        props[0],
+                        dict_size,
+                        None,
+                    )?;
+                    Ok(Decompressor::Lzma(Box::new(lzma_reader)))
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for Decompressor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Decompressor::Uncompressed(reader) => reader.read(buf),
+            Decompressor::Lzss(reader) => reader.read(buf),
+            Decompressor::Lzma(reader) => reader.read(buf),
+            Decompressor::Lzma2(reader) => reader.read(buf),
+        }
+    }
+}
+
+/// Streaming LZSS decoder of the same format written by [`lzss_compress`], decoding only what is
+/// read
+pub struct LzssReader<R> {
+    reader: R,
+    buffer: Box<[u8; LZSS_N]>,
+    r: usize,
+    flags: usize,
+    match_pos: usize,
+    match_len: usize,
+    eof: bool,
+}
+
+impl<R: Read> LzssReader<R> {
+    pub fn new(reader: R) -> Self {
+        LzssReader {
+            reader,
+            buffer: Box::new([0x00; LZSS_N]),
+            r: LZSS_N - LZSS_F,
+            flags: 0,
+            match_pos: 0,
+            match_len: 0,
+            eof: false,
+        }
+    }
+
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        let mut byte = [0u8; 1];
+        loop {
+            match self.reader.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn push(&mut self, byte: u8) -> u8 {
+        self.buffer[self.r] = byte;
+        self.r = (self.r + 1) & (LZSS_N - 1);
+        byte
+    }
+
+    fn next_byte(&mut self) -> std::io::Result<Option<u8>> {
+        if self.match_len > 0 {
+            let byte = self.buffer[self.match_pos & (LZSS_N - 1)];
+            self.match_pos += 1;
+            self.match_len -= 1;
+            return Ok(Some(self.push(byte)));
+        }
+        if self.eof {
+            return Ok(None);
+        }
+
+        self.flags >>= 1;
+        if self.flags & 256 == 0 {
+            let Some(byte) = self.read_byte()? else {
+                self.eof = true;
+                return Ok(None);
+            };
+            self.flags = byte as usize | 0xFF00;
+        }
+
+        if self.flags & 1 != 0 {
+            let Some(byte) = self.read_byte()? else {
+                self.eof = true;
+                return Ok(None);
+            };
+            Ok(Some(self.push(byte)))
+        } else {
+            let (Some(b1), Some(b2)) = (self.read_byte()?, self.read_byte()?) else {
+                self.eof = true;
+                return Ok(None);
+            };
+            self.match_pos = b1 as usize | ((b2 as usize & 0xF0) << 4);
+            self.match_len = (b2 as usize & 0x0F) + LZSS_THRESHOLD + 1;
+            self.next_byte()
+        }
+    }
+}
+
+impl<R: Read> Read for LzssReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut read = 0;
+        while read < buf.len() {
+            match self.next_byte()? {
+                Some(byte) => buf[read] = byte,
+                None => break,
+            }
+            read += 1;
+        }
+        Ok(read)
+    }
+}
+
+pub fn lzss_compress<R: std::io::Read, W: std::io::Write>(
+    mut reader: R,
+    mut writer: W,
+) -> Result<(), Error> {
+    match Lzs::new(0x00).compress(
         lzs::IOSimpleReader::new(&mut reader),
         lzs::IOSimpleWriter::new(&mut writer),
     ) {
@@ -18,30 +401,50 @@ pub fn lzss_decompress<R: std::io::Read, W: std::io::Write>(
     }
 }
 
-pub fn lzma_decompress<R: std::io::Read, W: std::io::Write>(
+/// Compress with LZMA using the same framing of 7th Heaven: decompressed size and properties
+/// size (both 4 bytes), followed by the LZMA properties and the raw LZMA stream
+pub fn lzma_compress<R: std::io::Read, W: std::io::Write>(
     mut reader: R,
     mut writer: W,
+    preset: &LzmaPreset,
 ) -> Result<(), Error> {
-    let mut header_bytes = [0u8; 8];
-    reader.read_exact(&mut header_bytes)?;
-    let bytes: &[u8] = &mut header_bytes;
-    let (bytes, dec_size) = le_i32(bytes)?;
-    let (_, prop_size) = le_i32(bytes)?;
-    let mut buf_reader = std::io::BufReader::new(&mut reader);
-    match if prop_size < 5 {
-        lzma_rs::lzma2_decompress(&mut buf_reader, &mut writer)
-    } else {
-        let options = lzma_rs::decompress::Options {
-            unpacked_size: lzma_rs::decompress::UnpackedSize::UseProvided(Some(dec_size as u64)),
-            allow_incomplete: false,
-            memlimit: None,
-        };
-        lzma_rs::lzma_decompress_with_options(&mut buf_reader, &mut writer, &options)
-    } {
-        Err(LzmaError::IoError(e)) => Err(Error::Io(e)),
-        Err(LzmaError::HeaderTooShort(e)) => Err(Error::Io(e)),
-        Err(LzmaError::LzmaError(s)) => Err(Error::Io(std::io::Error::other(s))),
-        Err(LzmaError::XzError(s)) => Err(Error::Io(std::io::Error::other(s))),
-        Ok(()) => Ok(()),
-    }
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let dec_size = i32::try_from(data.len()).map_err(|_| Error::FileTooLarge(data.len() as u64))?;
+
+    let options = preset.lzma_options();
+    writer.write_all(&dec_size.to_le_bytes())?;
+    writer.write_all(&LZMA_PROPS_SIZE.to_le_bytes())?;
+    writer.write_all(&[options.get_props()])?;
+    writer.write_all(&options.dict_size.to_le_bytes())?;
+
+    let mut lzma_writer =
+        LzmaWriter::new(&mut writer, &options, false, false, Some(data.len() as u64))?;
+    lzma_writer.write_all(&data)?;
+    lzma_writer.finish()?;
+    Ok(())
+}
+
+/// Compress with LZMA2 using the same framing of LZMA, but with a properties size of 0 and the
+/// LZMA2 chunks right after it
+pub fn lzma2_compress<R: std::io::Read, W: std::io::Write>(
+    mut reader: R,
+    mut writer: W,
+    preset: &LzmaPreset,
+) -> Result<(), Error> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let dec_size = i32::try_from(data.len()).map_err(|_| Error::FileTooLarge(data.len() as u64))?;
+
+    let options = Lzma2Options {
+        lzma_options: preset.lzma_options(),
+        chunk_size: None,
+    };
+    writer.write_all(&dec_size.to_le_bytes())?;
+    writer.write_all(&LZMA2_PROPS_SIZE.to_le_bytes())?;
+
+    let mut lzma2_writer = Lzma2Writer::new(&mut writer, options);
+    lzma2_writer.write_all(&data)?;
+    lzma2_writer.finish()?;
+    Ok(())
 }
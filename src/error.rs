@@ -1,5 +1,7 @@
 use std::path::PathBuf;
 
+use iroga_format::FormatError;
+use iroga_format::iro_header::IroFlags;
 use thiserror::Error;
 
 
@@ -17,22 +19,30 @@ pub enum Error {
     InvalidUnicode(PathBuf),
     #[error("could not find default name from {0}")]
     CannotDetectDefaultName(PathBuf),
-    #[error("parsing error due to invalid iro flags {0}")]
-    InvalidIroFlags(i32),
-    #[error("parsing error due to invalid iro version {0}")]
-    InvalidIroVersion(i32),
-    #[error("failed to parse binary data")]
-    CannotParseBinary(nom::Err<::nom::error::Error<Vec<u8>>>),
-    #[error("parsing error due to invalid file flags {0}")]
-    InvalidFileFlags(i32),
+    #[error(transparent)]
+    Format(#[from] FormatError),
     #[error("invalid utf16 {0}")]
     InvalidUtf16(String),
     #[error("parent file path does not exists: {0}")]
     ParentPathDoesNotExist(PathBuf),
+    #[error("invalid compression {0}")]
+    InvalidCompression(String),
+    #[error("invalid compression rule {0}, expected <glob>=<compression>")]
+    InvalidCompressionRule(String),
+    #[error("file too large to be compressed: {0} bytes")]
+    FileTooLarge(u64),
+    #[error("file path too long: {0}")]
+    PathTooLong(String),
+    #[error("entry offset {0} is before the stream position {1}, entries overlap and cannot be read in a single pass")]
+    OffsetGoesBackwards(u64, u64),
+    #[error("archive is not a patch, its type is {0}")]
+    NotPatch(IroFlags),
+    #[error("invalid decompressed size {0} of an LZMA entry")]
+    InvalidDecompressedSize(i32),
 }
 
 impl From<nom::Err<nom::error::Error<&[u8]>>> for Error {
     fn from(err: nom::Err<nom::error::Error<&[u8]>>) -> Self {
-        Self::CannotParseBinary(err.map_input(|input| input.into()))
+        Self::Format(err.into())
     }
 }
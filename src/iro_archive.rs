@@ -1,45 +1,60 @@
 use std::{
-    io::{BufReader, Read, Seek, Write},
+    borrow::Cow,
+    collections::HashMap,
+    io::{BufReader, Cursor, Read, Seek, SeekFrom, Write},
     result::Result,
 };
 
+use iroga_format::FormatError;
+use iroga_format::iro_parser::{min_entry_len, parse_iro_entry, parse_iro_header_v2};
+use nom::Needed;
+
 use crate::Error;
-use crate::compression;
-use crate::iro_entry::FileFlags;
-pub use crate::iro_entry::IroEntry;
-pub use crate::iro_header::IroHeader;
-use crate::iro_parser::{parse_iro_entry_v2, parse_iro_header_v2};
+use crate::compression::{self, Decompressor};
+use crate::read_at::{ReadAt, ReadAtCursor};
+pub use iroga_format::iro_entry::{FileFlags, IroEntry, IroEntryRef};
+pub use iroga_format::iro_header::{IroFlags, IroHeader, IroVersion};
+
+/// Length of the header section
+pub(crate) const HEADER_LEN: usize = 20;
 
+/// Maximum number of bytes of the index section read at once
+const MAX_INDEX_READ_LEN: usize = 1 << 20;
+
+/// Handle of an IRO archive whose header and index are loaded once on open
 pub struct IroArchive<RW> {
     stream: RW,
+    index: IroIndex,
 }
 
 impl<R: Read + Seek> IroArchive<R> {
-    pub fn open(stream: R) -> Self {
-        IroArchive { stream }
+    /// Read the header and the index of the archive
+    pub fn open(mut stream: R) -> Result<Self, Error> {
+        let header = read_header(&mut stream)?;
+        let entries = read_seekable_iro_entries(&mut stream, &header)?;
+        Ok(IroArchive {
+            stream,
+            index: IroIndex::new(header, entries)?,
+        })
     }
 
-    pub fn read_header(&mut self) -> Result<IroHeader, Error> {
-        let mut iro_header_bytes = [0u8; 20];
-        self.stream.read_exact(&mut iro_header_bytes)?;
-        let (_, iro_header) = parse_iro_header_v2(&iro_header_bytes)?;
-        Ok(iro_header)
+    pub fn header(&self) -> &IroHeader {
+        &self.index.header
     }
 
-    pub fn read_iro_entries(&mut self, iro_header: &IroHeader) -> Result<Vec<IroEntry>, Error> {
-        let mut iro_entries: Vec<IroEntry> = Vec::new();
-        for _ in 0..iro_header.num_files {
-            let mut entry_len_bytes = [0u8; 2];
-            self.stream.read_exact(&mut entry_len_bytes)?;
-            let entry_len = u16::from_le_bytes(entry_len_bytes);
+    /// Entries in the same order of the index section
+    pub fn entries(&self) -> &[IroEntry] {
+        &self.index.entries
+    }
 
-            let mut entry_bytes = vec![0u8; entry_len as usize - 2];
-            self.stream.read_exact(entry_bytes.as_mut())?;
+    pub fn by_index(&self, index: usize) -> Option<&IroEntry> {
+        self.index.entries.get(index)
+    }
 
-            let (_, iro_entry) = parse_iro_entry_v2(iro_header, &entry_bytes)?;
-            iro_entries.push(iro_entry);
-        }
-        Ok(iro_entries)
+    /// Entry with the given path (e.g. `field/md1stin`), compared case-insensitively and
+    /// accepting both `/` and `\` as separator
+    pub fn by_path(&self, path: &str) -> Option<&IroEntry> {
+        self.index.by_path(path)
     }
 
     pub fn seek_and_read_file_entry<W: Write>(
@@ -49,14 +64,400 @@ impl<R: Read + Seek> IroArchive<R> {
     ) -> Result<(), Error> {
         let mut buf_reader = BufReader::new(&mut self.stream);
         buf_reader.seek(std::io::SeekFrom::Start(iro_entry.offset))?;
-        let mut entry_buffer = buf_reader.take(iro_entry.data_len as u64);
-        match iro_entry.flags {
-            FileFlags::LzssCompressed => compression::lzss_decompress(&mut entry_buffer, writer)?,
-            FileFlags::LzmaCompressed => compression::lzma_decompress(&mut entry_buffer, writer)?,
-            _ => {
-                std::io::copy(&mut entry_buffer, writer)?;
+        let entry_buffer = buf_reader.take(iro_entry.data_len as u64);
+        compression::decompress(iro_entry.flags, entry_buffer, writer)
+    }
+
+    /// Open the data of the entry as stored inside the archive, i.e. still compressed
+    pub fn open_raw_entry(&mut self, iro_entry: &IroEntry) -> Result<std::io::Take<&mut R>, Error> {
+        self.stream.seek(SeekFrom::Start(iro_entry.offset))?;
+        Ok((&mut self.stream).take(iro_entry.data_len as u64))
+    }
+
+    /// Open the data of the entry as a reader decompressing it lazily.
+    ///
+    /// Uncompressed entries can also be seeked within the bounds of the entry.
+    pub fn open_entry(&mut self, iro_entry: &IroEntry) -> Result<IroEntryReader<&mut R>, Error> {
+        self.stream.seek(SeekFrom::Start(iro_entry.offset))?;
+        IroEntryReader::new(&mut self.stream, iro_entry)
+    }
+}
+
+impl<R: ReadAt> IroArchive<R> {
+    /// Same as [`IroArchive::open_entry`], but reading with positional reads through a shared
+    /// reference, so that many entries can be read at once from different threads
+    pub fn open_entry_at(
+        &self,
+        iro_entry: &IroEntry,
+    ) -> Result<IroEntryReader<ReadAtCursor<'_, R>>, Error> {
+        IroEntryReader::new(ReadAtCursor::new(&self.stream, iro_entry.offset), iro_entry)
+    }
+
+    /// Same as [`IroArchive::open_raw_entry`], but reading with positional reads through a shared
+    /// reference
+    pub fn open_raw_entry_at(&self, iro_entry: &IroEntry) -> std::io::Take<ReadAtCursor<'_, R>> {
+        ReadAtCursor::new(&self.stream, iro_entry.offset).take(iro_entry.data_len as u64)
+    }
+}
+
+impl<'a> IroArchive<Cursor<&'a [u8]>> {
+    /// Open an archive already loaded in memory
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, Error> {
+        IroArchive::open(Cursor::new(bytes))
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl IroArchive<Cursor<memmap2::Mmap>> {
+    /// Open an archive file by mapping it in memory
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while it is mapped, see [`memmap2::Mmap`]
+    pub unsafe fn open_mmap(file: &std::fs::File) -> Result<Self, Error> {
+        let mmap = unsafe { memmap2::Mmap::map(file)? };
+        IroArchive::open(Cursor::new(mmap))
+    }
+}
+
+impl<T: AsRef<[u8]>> IroArchive<Cursor<T>> {
+    /// Bytes of the entry as stored inside the archive, i.e. still compressed
+    pub fn raw_entry_bytes(&self, iro_entry: &IroEntry) -> Result<&[u8], Error> {
+        let bytes = self.stream.get_ref().as_ref();
+        usize::try_from(iro_entry.offset)
+            .ok()
+            .and_then(|start| bytes.get(start..start.checked_add(iro_entry.data_len as usize)?))
+            .ok_or(Error::Io(std::io::ErrorKind::UnexpectedEof.into()))
+    }
+
+    /// Entries borrowing their paths from the archive bytes, parsed lazily while iterating
+    pub fn entry_refs(&self) -> Result<IroEntryRefs<'_>, Error> {
+        IroEntryRefs::new(self.stream.get_ref().as_ref())
+    }
+
+    /// Decompressed bytes of the entry, borrowed with no copy when the entry is uncompressed
+    pub fn entry_bytes(&self, iro_entry: &IroEntry) -> Result<Cow<'_, [u8]>, Error> {
+        let raw_bytes = self.raw_entry_bytes(iro_entry)?;
+        if iro_entry.flags == FileFlags::Uncompressed {
+            return Ok(Cow::Borrowed(raw_bytes));
+        }
+        let mut bytes = Vec::new();
+        Decompressor::new(iro_entry.flags, raw_bytes)?.read_to_end(&mut bytes)?;
+        Ok(Cow::Owned(bytes))
+    }
+}
+
+/// Iterator parsing lazily the index section of an archive in memory, with no allocation
+pub struct IroEntryRefs<'a> {
+    header: IroHeader,
+    bytes: &'a [u8],
+    remaining: u32,
+}
+
+impl<'a> IroEntryRefs<'a> {
+    /// Parse the header of the archive bytes, the entries are parsed only while iterating
+    pub fn new(archive_bytes: &'a [u8]) -> Result<Self, Error> {
+        let (bytes, header) = parse_iro_header_v2(archive_bytes)?;
+        Ok(IroEntryRefs {
+            remaining: header.num_files,
+            header,
+            bytes,
+        })
+    }
+
+    pub fn header(&self) -> &IroHeader {
+        &self.header
+    }
+}
+
+impl<'a> Iterator for IroEntryRefs<'a> {
+    type Item = Result<IroEntryRef<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        match parse_iro_entry(&self.header, self.bytes) {
+            Ok((bytes, iro_entry)) => {
+                self.bytes = bytes;
+                self.remaining -= 1;
+                Some(Ok(iro_entry))
             }
+            // stop at the first error, the following entries cannot be located anymore
+            Err(err) => {
+                self.remaining = 0;
+                Some(Err(match err {
+                    FormatError::CannotParseBinary(nom::Err::Incomplete(_)) => {
+                        Error::Io(std::io::ErrorKind::UnexpectedEof.into())
+                    }
+                    err => err.into(),
+                }))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining as usize))
+    }
+}
+
+/// Header and entries of an archive, indexed by path
+pub(crate) struct IroIndex {
+    pub(crate) header: IroHeader,
+    pub(crate) entries: Vec<IroEntry>,
+    /// Index of the entries by lowercase and backslash-separated path
+    path_index: HashMap<String, usize>,
+}
+
+impl IroIndex {
+    pub(crate) fn new(header: IroHeader, entries: Vec<IroEntry>) -> Result<Self, Error> {
+        let mut path_index = HashMap::with_capacity(entries.len());
+        for (index, iro_entry) in entries.iter().enumerate() {
+            let path = crate::parse_utf16(&iro_entry.path)?;
+            // the first entry wins when the same path is stored twice
+            path_index.entry(normalize_path(&path)).or_insert(index);
+        }
+        Ok(IroIndex {
+            header,
+            entries,
+            path_index,
+        })
+    }
+
+    pub(crate) fn by_path(&self, path: &str) -> Option<&IroEntry> {
+        self.path_index
+            .get(&normalize_path(path))
+            .map(|index| &self.entries[*index])
+    }
+}
+
+/// Path used as key of the path index: lowercase and backslash-separated, as FF7 paths are
+pub(crate) fn normalize_path(path: &str) -> String {
+    path.replace('/', "\\").to_lowercase()
+}
+
+/// Reader of the decompressed data of an entry, see [`IroArchive::open_entry`]
+pub struct IroEntryReader<S> {
+    decompressor: Decompressor<EntryData<S>>,
+}
+
+impl<S: Read + Seek> IroEntryReader<S> {
+    /// Create the reader of the entry from a stream already positioned at its offset
+    fn new(stream: S, iro_entry: &IroEntry) -> Result<Self, Error> {
+        let entry_data = EntryData {
+            stream,
+            offset: iro_entry.offset,
+            len: iro_entry.data_len as u64,
+            position: 0,
         };
-        Ok(())
+        Ok(IroEntryReader {
+            decompressor: Decompressor::new(iro_entry.flags, entry_data)?,
+        })
+    }
+
+    /// Whether the reader supports [`Seek`], i.e. the entry is uncompressed
+    pub fn is_seekable(&self) -> bool {
+        matches!(self.decompressor, Decompressor::Uncompressed(_))
+    }
+}
+
+impl<S: Read + Seek> Read for IroEntryReader<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.decompressor.read(buf)
+    }
+}
+
+impl<S: Read + Seek> Seek for IroEntryReader<S> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match &mut self.decompressor {
+            Decompressor::Uncompressed(entry_data) => entry_data.seek(pos),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "only uncompressed entries can be seeked",
+            )),
+        }
+    }
+}
+
+/// Raw data of an entry, bounded to its offset and length inside the archive
+struct EntryData<S> {
+    stream: S,
+    offset: u64,
+    len: u64,
+    position: u64,
+}
+
+impl<S: Read> Read for EntryData<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let max_len = buf.len().min((self.len - self.position) as usize);
+        let read = self.stream.read(&mut buf[..max_len])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<S: Seek> Seek for EntryData<S> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+        };
+        let position = position.filter(|position| *position <= self.len).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "seek position out of the entry bounds",
+            )
+        })?;
+        self.stream.seek(SeekFrom::Start(self.offset + position))?;
+        self.position = position;
+        Ok(position)
+    }
+}
+
+pub(crate) fn read_header<R: Read>(stream: &mut R) -> Result<IroHeader, Error> {
+    let mut iro_header_bytes = [0u8; HEADER_LEN];
+    stream.read_exact(&mut iro_header_bytes)?;
+    let (_, iro_header) = parse_iro_header_v2(&iro_header_bytes)?;
+    Ok(iro_header)
+}
+
+/// Read the whole index section with as few reads as possible, without reading past its end.
+///
+/// Returns the entries with the length in bytes of the index section.
+pub(crate) fn read_iro_entries<R: Read>(
+    stream: &mut R,
+    iro_header: &IroHeader,
+) -> Result<(Vec<IroEntry>, u64), Error> {
+    let mut index_parser = IndexParser::new(iro_header);
+    while let Some(buffer) = index_parser.next_buffer()? {
+        stream.read_exact(buffer)?;
+    }
+    Ok(index_parser.finish())
+}
+
+/// Read the whole index section with as few reads as possible, possibly reading past its end
+/// since the stream is then seeked to the data of each entry
+pub(crate) fn read_seekable_iro_entries<R: Read + Seek>(
+    stream: &mut R,
+    iro_header: &IroHeader,
+) -> Result<Vec<IroEntry>, Error> {
+    let position = stream.stream_position()?;
+    let stream_len = stream.seek(SeekFrom::End(0))?;
+    stream.seek(SeekFrom::Start(position))?;
+    let mut index_parser = IndexParser::for_seekable(iro_header, stream_len);
+    while let Some(buffer) = index_parser.next_buffer()? {
+        stream.read_exact(buffer)?;
+    }
+    let (iro_entries, _) = index_parser.finish();
+    Ok(iro_entries)
+}
+
+/// Parser of the index section fed with the bytes it asks for, shared by every reader.
+///
+/// Every remaining entry takes at least [`min_entry_len`] bytes, so that much can always be read
+/// at once without reading past the index: the number of reads grows with the logarithm of the
+/// number of entries.
+///
+/// Streams that can seek may read past the index, so the data section is assumed to start at the
+/// offset of the first entry, right after the index, and the rest of the index is read at once
+/// after the first entry. The growth above is only used when that offset is not plausible (e.g.
+/// past the end of the stream).
+pub(crate) struct IndexParser<'a> {
+    iro_header: &'a IroHeader,
+    iro_entries: Vec<IroEntry>,
+    index_bytes: Vec<u8>,
+    parsed_len: usize,
+    /// Length of the entries parsed so far, padding included
+    index_len: u64,
+    read_len: usize,
+    stream_len: Option<u64>,
+}
+
+impl<'a> IndexParser<'a> {
+    pub(crate) fn new(iro_header: &'a IroHeader) -> Self {
+        let num_files = iro_header.num_files as usize;
+        IndexParser {
+            iro_header,
+            iro_entries: Vec::with_capacity(num_files.min(MAX_INDEX_READ_LEN / 16)),
+            index_bytes: Vec::new(),
+            parsed_len: 0,
+            index_len: 0,
+            read_len: 0,
+            stream_len: None,
+        }
+    }
+
+    /// Parser of the index of a stream that can seek with the given length, which may read past
+    /// the end of the index
+    pub(crate) fn for_seekable(iro_header: &'a IroHeader, stream_len: u64) -> Self {
+        IndexParser {
+            stream_len: Some(stream_len),
+            ..IndexParser::new(iro_header)
+        }
+    }
+
+    /// Parse the entries of the bytes read so far, then return the buffer to fill completely
+    /// with the next bytes of the index, or `None` when all the entries have been parsed
+    pub(crate) fn next_buffer(&mut self) -> Result<Option<&mut [u8]>, Error> {
+        let num_files = self.iro_header.num_files as usize;
+        while self.iro_entries.len() < num_files {
+            match parse_iro_entry(self.iro_header, &self.index_bytes[self.parsed_len..]) {
+                Ok((bytes, iro_entry)) => {
+                    let parsed_len = self.index_bytes.len() - bytes.len();
+                    self.index_len += (parsed_len - self.parsed_len) as u64;
+                    self.parsed_len = parsed_len;
+                    self.iro_entries.push(iro_entry.into());
+                }
+                Err(FormatError::CannotParseBinary(nom::Err::Incomplete(needed))) => {
+                    let needed = match needed {
+                        Needed::Size(size) => size.get(),
+                        Needed::Unknown => 1,
+                    };
+                    let unparsed_len = self.index_bytes.len() - self.parsed_len;
+                    let remaining_entries = num_files - self.iro_entries.len();
+                    let remaining_len = remaining_entries
+                        .saturating_mul(min_entry_len(self.iro_header))
+                        .saturating_sub(unparsed_len);
+                    let min_read_len = needed.max(remaining_len);
+                    let max_read_len = remaining_entries
+                        .saturating_mul(u16::MAX as usize)
+                        .saturating_sub(unparsed_len);
+                    let read_len = match self.first_offset_read_len() {
+                        Some(read_len) if (min_read_len..=max_read_len).contains(&read_len) => {
+                            read_len
+                        }
+                        _ => needed.max(remaining_len.min(MAX_INDEX_READ_LEN)),
+                    };
+
+                    self.index_bytes.drain(..self.parsed_len);
+                    self.parsed_len = 0;
+                    self.read_len += read_len;
+                    let start = self.index_bytes.len();
+                    self.index_bytes.resize(start + read_len, 0);
+                    return Ok(Some(&mut self.index_bytes[start..]));
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Length of the index left to read when it ends at the offset of the first entry
+    fn first_offset_read_len(&self) -> Option<usize> {
+        let stream_len = self.stream_len?;
+        let first_entry = self.iro_entries.first()?;
+        if first_entry.offset > stream_len {
+            return None;
+        }
+        let index_end = first_entry.offset.checked_sub(HEADER_LEN as u64)?;
+        let read_len = index_end.checked_sub(self.read_len as u64)?;
+        usize::try_from(read_len).ok()
+    }
+
+    /// Parsed entries with the length in bytes of the index section, which may be longer than
+    /// the paths and fixed fields of the entries
+    pub(crate) fn finish(self) -> (Vec<IroEntry>, u64) {
+        (self.iro_entries, self.index_len)
     }
 }
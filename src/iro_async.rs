@@ -0,0 +1,264 @@
+use std::{
+    io::{Cursor, Read, SeekFrom},
+    pin::Pin,
+};
+
+use iroga_format::iro_parser::parse_iro_header_v2;
+use tokio::io::{
+    AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt, BufWriter,
+};
+
+use crate::Error;
+use crate::compression::{self, Compression, Decompressor};
+use crate::iro_archive::{
+    FileFlags, HEADER_LEN, IndexParser, IroEntry, IroFlags, IroHeader, IroIndex, IroVersion,
+};
+use crate::iro_writer::{PackedEntry, PendingEntries};
+
+const BUFFER_SIZE: usize = 1 << 16;
+
+/// Async version of [`IroArchive`](crate::iro_archive::IroArchive) for tokio streams.
+///
+/// Header, index and entry data are read asynchronously, while the decompression runs on the
+/// blocking thread pool of tokio so that it does not stall the other tasks.
+pub struct AsyncIroArchive<R> {
+    stream: R,
+    index: IroIndex,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncIroArchive<R> {
+    /// Read the header and the index of the archive
+    pub async fn open(mut stream: R) -> Result<Self, Error> {
+        let mut header_bytes = [0u8; HEADER_LEN];
+        stream.read_exact(&mut header_bytes).await?;
+        let (_, header) = parse_iro_header_v2(&header_bytes)?;
+
+        let stream_len = stream.seek(SeekFrom::End(0)).await?;
+        stream.seek(SeekFrom::Start(HEADER_LEN as u64)).await?;
+        let mut index_parser = IndexParser::for_seekable(&header, stream_len);
+        while let Some(buffer) = index_parser.next_buffer()? {
+            stream.read_exact(buffer).await?;
+        }
+        let (entries, _) = index_parser.finish();
+
+        Ok(AsyncIroArchive {
+            stream,
+            index: IroIndex::new(header, entries)?,
+        })
+    }
+
+    pub fn header(&self) -> &IroHeader {
+        &self.index.header
+    }
+
+    /// Entries in the same order of the index section
+    pub fn entries(&self) -> &[IroEntry] {
+        &self.index.entries
+    }
+
+    pub fn by_index(&self, index: usize) -> Option<&IroEntry> {
+        self.index.entries.get(index)
+    }
+
+    /// Entry with the given path (e.g. `field/md1stin`), compared case-insensitively and
+    /// accepting both `/` and `\` as separator
+    pub fn by_path(&self, path: &str) -> Option<&IroEntry> {
+        self.index.by_path(path)
+    }
+
+    /// Open the data of the entry as stored inside the archive, i.e. still compressed
+    pub async fn open_raw_entry(
+        &mut self,
+        iro_entry: &IroEntry,
+    ) -> Result<tokio::io::Take<&mut R>, Error> {
+        self.stream.seek(SeekFrom::Start(iro_entry.offset)).await?;
+        Ok((&mut self.stream).take(iro_entry.data_len as u64))
+    }
+
+    /// Bytes of the entry as stored inside the archive, i.e. still compressed
+    pub async fn read_raw_entry(&mut self, iro_entry: &IroEntry) -> Result<Vec<u8>, Error> {
+        // the buffer grows with the bytes actually read rather than the length of the index
+        let mut raw_bytes = Vec::new();
+        let mut raw_reader = self.open_raw_entry(iro_entry).await?;
+        raw_reader.read_to_end(&mut raw_bytes).await?;
+        if raw_bytes.len() != iro_entry.data_len as usize {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(raw_bytes)
+    }
+
+    /// Decompressed bytes of the entry
+    pub async fn read_entry(&mut self, iro_entry: &IroEntry) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::new();
+        self.copy_entry(iro_entry, &mut bytes).await?;
+        Ok(bytes)
+    }
+
+    /// Decompress the entry into the writer.
+    ///
+    /// Uncompressed entries are copied in chunks, compressed entries are decompressed in chunks
+    /// on the blocking thread pool of tokio.
+    pub async fn copy_entry<W: AsyncWrite + Unpin>(
+        &mut self,
+        iro_entry: &IroEntry,
+        writer: &mut W,
+    ) -> Result<(), Error> {
+        if iro_entry.flags == FileFlags::Uncompressed {
+            let mut raw_reader = self.open_raw_entry(iro_entry).await?;
+            let copied_len = tokio::io::copy(&mut raw_reader, writer).await?;
+            if copied_len != iro_entry.data_len as u64 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+            return Ok(());
+        }
+
+        let raw_bytes = self.read_raw_entry(iro_entry).await?;
+        let mut decompressor = Decompressor::new(iro_entry.flags, Cursor::new(raw_bytes))?;
+        loop {
+            let (next_decompressor, chunk) = run_blocking(move || {
+                let mut chunk = Vec::with_capacity(BUFFER_SIZE);
+                (&mut decompressor)
+                    .take(BUFFER_SIZE as u64)
+                    .read_to_end(&mut chunk)?;
+                Ok((decompressor, chunk))
+            })
+            .await?;
+            if chunk.is_empty() {
+                return Ok(());
+            }
+            writer.write_all(&chunk).await?;
+            decompressor = next_decompressor;
+        }
+    }
+}
+
+/// Async version of [`IroWriter`](crate::iro_writer::IroWriter) for tokio streams.
+///
+/// Entries are read, compressed and written one at a time once the archive is finished, the
+/// compression runs on the blocking thread pool of tokio while uncompressed entries are copied
+/// in chunks.
+pub struct AsyncIroWriter<'a, W> {
+    stream: W,
+    entries: PendingEntries<EntrySource<'a>>,
+}
+
+enum EntrySource<'a> {
+    Bytes(&'a [u8]),
+    Reader(Pin<Box<dyn AsyncRead + Send + 'a>>),
+}
+
+impl<'a, W: AsyncWrite + AsyncSeek + Unpin> AsyncIroWriter<'a, W> {
+    pub fn new(stream: W) -> Self {
+        AsyncIroWriter {
+            stream,
+            entries: PendingEntries::new(),
+        }
+    }
+
+    /// Version of the written archive (default is the latest version 0x10002).
+    ///
+    /// Finishing fails when an offset exceeds the 4 bytes of version 0x10000.
+    pub fn set_version(&mut self, version: IroVersion) {
+        self.entries.set_version(version);
+    }
+
+    /// Flags of the written archive (default is a full archive, [`IroFlags::Patch`] for patches)
+    pub fn set_flags(&mut self, flags: IroFlags) {
+        self.entries.set_flags(flags);
+    }
+
+    /// Add an entry with the given archive path (e.g. `field/md1stin`) from in-memory bytes
+    pub fn add_bytes(
+        &mut self,
+        path: &str,
+        data: &'a [u8],
+        compression: Compression,
+    ) -> Result<(), Error> {
+        self.entries
+            .push(path, compression, EntrySource::Bytes(data))
+    }
+
+    /// Add an entry with the given archive path from a reader, read only when finishing
+    pub fn add_reader<R: AsyncRead + Send + 'a>(
+        &mut self,
+        path: &str,
+        reader: R,
+        compression: Compression,
+    ) -> Result<(), Error> {
+        self.entries
+            .push(path, compression, EntrySource::Reader(Box::pin(reader)))
+    }
+
+    /// Compress and write all the entries, returning the inner stream, the written header and the
+    /// written entries
+    pub async fn finish(self) -> Result<(W, IroHeader, Vec<PackedEntry>), Error> {
+        let (mut layout, entries) = self.entries.into_layout();
+        let mut stream = BufWriter::new(self.stream);
+        stream.write_all(&layout.header_bytes()).await?;
+        stream.seek(SeekFrom::Start(layout.offset())).await?;
+
+        let mut packed_entries = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let (size, flags, data_len) = match (entry.compression, entry.source) {
+                (Compression::None, EntrySource::Bytes(bytes)) => {
+                    stream.write_all(bytes).await?;
+                    let len = bytes.len() as u64;
+                    (len, FileFlags::Uncompressed, len)
+                }
+                (Compression::None, EntrySource::Reader(mut reader)) => {
+                    let len = tokio::io::copy(&mut reader, &mut stream).await?;
+                    (len, FileFlags::Uncompressed, len)
+                }
+                (compression, source) => {
+                    let data = match source {
+                        EntrySource::Bytes(bytes) => bytes.to_vec(),
+                        EntrySource::Reader(mut reader) => {
+                            let mut bytes = Vec::new();
+                            reader.read_to_end(&mut bytes).await?;
+                            bytes
+                        }
+                    };
+                    let size = data.len() as u64;
+                    let (flags, compressed) = run_blocking(move || {
+                        let mut compressed = Vec::new();
+                        let flags =
+                            compression::compress(compression, data.as_slice(), &mut compressed)?;
+                        Ok((flags, compressed))
+                    })
+                    .await?;
+                    stream.write_all(&compressed).await?;
+                    (size, flags, compressed.len() as u64)
+                }
+            };
+            let iro_entry = layout.append_entry(flags, data_len as usize)?;
+            packed_entries.push(PackedEntry {
+                path: entry.path,
+                flags,
+                offset: iro_entry.offset,
+                size,
+                stored_size: iro_entry.data_len,
+            });
+        }
+
+        let iro_header = layout.header();
+        let end_offset = layout.offset();
+        stream.seek(SeekFrom::Start(HEADER_LEN as u64)).await?;
+        stream.write_all(&layout.index_bytes()?).await?;
+        stream.seek(SeekFrom::Start(end_offset)).await?;
+        stream.flush().await?;
+        Ok((stream.into_inner(), iro_header, packed_entries))
+    }
+}
+
+/// Run the CPU-bound `job` on the blocking thread pool of tokio, resuming its panic if any
+async fn run_blocking<T, F>(job: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+{
+    match tokio::task::spawn_blocking(job).await {
+        Ok(result) => result,
+        Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+        Err(err) => Err(Error::Io(std::io::Error::other(err))),
+    }
+}
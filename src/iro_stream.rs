@@ -0,0 +1,105 @@
+use std::{
+    io::{BufReader, Read, Write},
+    result::Result,
+};
+
+use crate::Error;
+use crate::compression;
+use crate::iro_archive::{IroEntry, IroHeader, read_header, read_iro_entries};
+
+/// Reader of an IRO archive from a non-seekable stream (e.g. stdin).
+///
+/// Header and index are read first, then the entries are extracted in a single forward pass
+/// ordered by offset, skipping the gaps between them.
+pub struct IroStreamReader<R> {
+    stream: BufReader<R>,
+    position: u64,
+}
+
+impl<R: Read> IroStreamReader<R> {
+    pub fn new(stream: R) -> Self {
+        IroStreamReader {
+            stream: BufReader::new(stream),
+            position: 0,
+        }
+    }
+
+    pub fn read_header(&mut self) -> Result<IroHeader, Error> {
+        let mut counting_stream = CountingReader::new(&mut self.stream);
+        let iro_header = read_header(&mut counting_stream)?;
+        self.position += counting_stream.count;
+        Ok(iro_header)
+    }
+
+    pub fn read_iro_entries(&mut self, iro_header: &IroHeader) -> Result<Vec<IroEntry>, Error> {
+        let mut counting_stream = CountingReader::new(&mut self.stream);
+        let (iro_entries, _) = read_iro_entries(&mut counting_stream, iro_header)?;
+        self.position += counting_stream.count;
+        Ok(iro_entries)
+    }
+
+    /// Decompress the entries ordered by offset into the writers opened by `open_writer`, entries
+    /// without a writer are skipped.
+    ///
+    /// Fails when an entry starts before the end of the previous one (e.g. overlapping entries),
+    /// since a stream cannot go backwards.
+    pub fn read_file_entries<W, F>(
+        &mut self,
+        mut iro_entries: Vec<IroEntry>,
+        mut open_writer: F,
+    ) -> Result<(), Error>
+    where
+        W: Write,
+        F: FnMut(&IroEntry) -> Result<Option<W>, Error>,
+    {
+        iro_entries.sort_by_key(|iro_entry| iro_entry.offset);
+        for iro_entry in iro_entries {
+            if iro_entry.offset < self.position {
+                return Err(Error::OffsetGoesBackwards(iro_entry.offset, self.position));
+            }
+            self.skip(iro_entry.offset - self.position)?;
+
+            let mut entry_buffer =
+                CountingReader::new((&mut self.stream).take(iro_entry.data_len as u64));
+            if let Some(mut writer) = open_writer(&iro_entry)? {
+                compression::decompress(iro_entry.flags, &mut entry_buffer, &mut writer)?;
+                writer.flush()?;
+            }
+            std::io::copy(&mut entry_buffer, &mut std::io::sink())?;
+            if entry_buffer.count != iro_entry.data_len as u64 {
+                return Err(Error::Io(std::io::ErrorKind::UnexpectedEof.into()));
+            }
+            self.position = iro_entry.offset + iro_entry.data_len as u64;
+        }
+        Ok(())
+    }
+
+    fn skip(&mut self, len: u64) -> Result<(), Error> {
+        let skipped = std::io::copy(&mut (&mut self.stream).take(len), &mut std::io::sink())?;
+        if skipped != len {
+            return Err(Error::Io(std::io::ErrorKind::UnexpectedEof.into()));
+        }
+        self.position += len;
+        Ok(())
+    }
+}
+
+/// Reader keeping track of the number of bytes read
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> CountingReader<R> {
+    fn new(inner: R) -> Self {
+        CountingReader { inner, count: 0 }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
@@ -0,0 +1,478 @@
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use iroga_format::FormatError;
+use iroga_format::iro_entry::{FileFlags, INDEX_FIXED_BYTE_SIZE, IroEntry};
+use iroga_format::iro_header::{IroFlags, IroHeader, IroVersion};
+
+use crate::Error;
+use crate::compression::{self, Compression};
+use crate::iro_archive::{HEADER_LEN, IroArchive};
+use crate::pipeline;
+
+const BUFFER_SIZE: usize = 1 << 16;
+
+/// Writer of an IRO archive into any stream (e.g. a `File` or a `Cursor<Vec<u8>>`).
+///
+/// Entries are only collected when added: their data is read, compressed and written once the
+/// archive is finished, since the index section of all entries comes before the data section.
+/// Streams that cannot seek (e.g. stdout) are supported through [`IroWriter::finish_streaming`].
+pub struct IroWriter<'a, W> {
+    stream: W,
+    threads: usize,
+    entries: PendingEntries<EntrySource<'a>>,
+}
+
+/// Entry written inside an IRO archive
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PackedEntry {
+    /// File path inside the archive
+    pub path: String,
+    /// Compression chosen for the file
+    pub flags: FileFlags,
+    /// Offset of the data inside the archive
+    pub offset: u64,
+    /// Size of the file before compression (the stored size for entries added already stored)
+    pub size: u64,
+    /// Size of the file stored inside the archive
+    pub stored_size: u32,
+}
+
+/// Entries added to a writer along with the version and flags of the archive, shared by every
+/// writer until the archive is finished
+pub(crate) struct PendingEntries<S> {
+    version: IroVersion,
+    flags: IroFlags,
+    entries: Vec<PendingEntry<S>>,
+}
+
+/// Entry added to a writer, whose data is read from `source` once the archive is finished
+pub(crate) struct PendingEntry<S> {
+    pub(crate) path: String,
+    unicode_path: Vec<u8>,
+    pub(crate) compression: Compression,
+    pub(crate) source: S,
+}
+
+impl<S> PendingEntries<S> {
+    pub(crate) fn new() -> Self {
+        PendingEntries {
+            version: IroVersion::default(),
+            flags: IroFlags::None,
+            entries: Vec::new(),
+        }
+    }
+
+    pub(crate) fn set_version(&mut self, version: IroVersion) {
+        self.version = version;
+    }
+
+    pub(crate) fn set_flags(&mut self, flags: IroFlags) {
+        self.flags = flags;
+    }
+
+    pub(crate) fn push(
+        &mut self,
+        path: &str,
+        compression: Compression,
+        source: S,
+    ) -> Result<(), Error> {
+        self.entries.push(PendingEntry {
+            path: path.to_owned(),
+            unicode_path: unicode_path_bytes(path)?,
+            compression,
+            source,
+        });
+        Ok(())
+    }
+
+    /// Layout of the archive with the paths of all the entries, along with the entries whose
+    /// data is then appended in order
+    pub(crate) fn into_layout(self) -> (ArchiveLayout, Vec<PendingEntry<S>>) {
+        let mut entries = self.entries;
+        let paths = entries
+            .iter_mut()
+            .map(|entry| std::mem::take(&mut entry.unicode_path))
+            .collect();
+        let layout = ArchiveLayout::new(paths, self.version).with_flags(self.flags);
+        (layout, entries)
+    }
+}
+
+enum EntrySource<'a> {
+    Bytes(&'a [u8]),
+    Reader(Box<dyn Read + Send + 'a>),
+    File(PathBuf),
+    Stored(Box<dyn Read + Send + 'a>, FileFlags),
+}
+
+impl<'a> EntrySource<'a> {
+    /// Reader of the source data, opening the file if any
+    fn into_reader(self) -> Result<Box<dyn Read + Send + 'a>, Error> {
+        Ok(match self {
+            EntrySource::Bytes(bytes) => Box::new(bytes),
+            EntrySource::Reader(reader) | EntrySource::Stored(reader, _) => reader,
+            EntrySource::File(file_path) => Box::new(File::open(file_path)?),
+        })
+    }
+}
+
+/// Data of an entry ready to be written, either already compressed (with its size before
+/// compression) or still to be read from its source
+enum EntryData<'a> {
+    Compressed(u64, FileFlags, Vec<u8>),
+    Streamed(Box<dyn Read + Send + 'a>, FileFlags),
+}
+
+impl<'a, W: Write> IroWriter<'a, W> {
+    pub fn new(stream: W) -> Self {
+        IroWriter {
+            stream,
+            threads: 1,
+            entries: PendingEntries::new(),
+        }
+    }
+
+    /// Number of threads used to compress the entries when finishing the archive
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads;
+    }
+
+    /// Version of the written archive (default is the latest version 0x10002).
+    ///
+    /// Finishing fails when an offset exceeds the 4 bytes of version 0x10000.
+    pub fn set_version(&mut self, version: IroVersion) {
+        self.entries.set_version(version);
+    }
+
+    /// Flags of the written archive (default is a full archive, [`IroFlags::Patch`] for patches)
+    pub fn set_flags(&mut self, flags: IroFlags) {
+        self.entries.set_flags(flags);
+    }
+
+    /// Add an entry with the given archive path (e.g. `field/md1stin`) from in-memory bytes
+    pub fn add_bytes(
+        &mut self,
+        path: &str,
+        data: &'a [u8],
+        compression: Compression,
+    ) -> Result<(), Error> {
+        self.add_entry(path, compression, EntrySource::Bytes(data))
+    }
+
+    /// Add an entry with the given archive path from a reader, read only when finishing
+    pub fn add_reader<R: Read + Send + 'a>(
+        &mut self,
+        path: &str,
+        reader: R,
+        compression: Compression,
+    ) -> Result<(), Error> {
+        self.add_entry(path, compression, EntrySource::Reader(Box::new(reader)))
+    }
+
+    /// Add an entry with the given archive path from a file, opened only when finishing
+    pub fn add_file(
+        &mut self,
+        path: &str,
+        file_path: impl Into<PathBuf>,
+        compression: Compression,
+    ) -> Result<(), Error> {
+        self.add_entry(path, compression, EntrySource::File(file_path.into()))
+    }
+
+    /// Add an entry whose data is already stored with the given flags (e.g. the raw data of
+    /// another archive entry), written as is without compressing it again
+    pub fn add_stored_reader<R: Read + Send + 'a>(
+        &mut self,
+        path: &str,
+        reader: R,
+        flags: FileFlags,
+    ) -> Result<(), Error> {
+        let source = EntrySource::Stored(Box::new(reader), flags);
+        self.add_entry(path, Compression::None, source)
+    }
+
+    fn add_entry(
+        &mut self,
+        path: &str,
+        compression: Compression,
+        source: EntrySource<'a>,
+    ) -> Result<(), Error> {
+        self.entries.push(path, compression, source)
+    }
+
+    /// Compress and write all the entries into a non-seekable stream, returning the inner stream
+    /// and the written entries.
+    ///
+    /// The archive is spooled into a temporary file first, then header, index and data are copied
+    /// strictly in order into the stream.
+    pub fn finish_streaming(self) -> Result<(W, IroHeader, Vec<PackedEntry>), Error> {
+        let IroWriter {
+            mut stream,
+            threads,
+            entries,
+        } = self;
+        let spool_writer = IroWriter {
+            stream: tempfile::tempfile()?,
+            threads,
+            entries,
+        };
+        let (mut spool, iro_header, packed_entries) = spool_writer.finish()?;
+        spool.seek(SeekFrom::Start(0))?;
+        std::io::copy(&mut spool, &mut stream)?;
+        stream.flush()?;
+        Ok((stream, iro_header, packed_entries))
+    }
+}
+
+impl<W: Write + Seek> IroWriter<'_, W> {
+    /// Compress and write all the entries, returning the inner stream, the written header and the
+    /// written entries
+    pub fn finish(self) -> Result<(W, IroHeader, Vec<PackedEntry>), Error> {
+        let (layout, entries) = self.entries.into_layout();
+        let mut data_writer = DataWriter::new(self.stream, layout)?;
+
+        // entries are compressed on the worker threads, then written in order. Entries that are
+        // not compressed are streamed from their source in the write step without buffering them.
+        let mut packed_entries = Vec::with_capacity(entries.len());
+        pipeline::ordered_parallel_map(
+            entries,
+            self.threads,
+            |entry| {
+                let data: Cow<[u8]> = match entry.source {
+                    EntrySource::Stored(reader, flags) => {
+                        return Ok((entry.path, EntryData::Streamed(reader, flags)));
+                    }
+                    source if entry.compression == Compression::None => {
+                        let reader = source.into_reader()?;
+                        let entry_data = EntryData::Streamed(reader, FileFlags::Uncompressed);
+                        return Ok((entry.path, entry_data));
+                    }
+                    EntrySource::Bytes(bytes) => Cow::Borrowed(bytes),
+                    source => {
+                        let mut bytes = Vec::new();
+                        source.into_reader()?.read_to_end(&mut bytes)?;
+                        Cow::Owned(bytes)
+                    }
+                };
+                let mut compressed = Vec::new();
+                let flags =
+                    compression::compress(entry.compression, data.as_ref(), &mut compressed)?;
+                let size = data.len() as u64;
+                Ok((entry.path, EntryData::Compressed(size, flags, compressed)))
+            },
+            |(path, entry_data)| {
+                let (size, iro_entry) = match entry_data {
+                    EntryData::Compressed(size, flags, compressed) => {
+                        (size, data_writer.write_entry(flags, &compressed)?)
+                    }
+                    EntryData::Streamed(reader, flags) => {
+                        let iro_entry = data_writer.stream_entry(flags, reader)?;
+                        (iro_entry.data_len as u64, iro_entry)
+                    }
+                };
+                packed_entries.push(PackedEntry {
+                    path,
+                    flags: iro_entry.flags,
+                    offset: iro_entry.offset,
+                    size,
+                    stored_size: iro_entry.data_len,
+                });
+                Ok(())
+            },
+        )?;
+
+        let (stream, iro_header) = data_writer.finish()?;
+        Ok((stream, iro_header, packed_entries))
+    }
+}
+
+/// Write a copy of the archive in the given IRO version, returning the inner stream and the
+/// written header.
+///
+/// The header flags and the entries are kept as they are, the data of each entry is copied
+/// byte-for-byte without decompressing it.
+pub fn convert_archive<R: Read + Seek, W: Write + Seek>(
+    iro_archive: &mut IroArchive<R>,
+    stream: W,
+    version: IroVersion,
+) -> Result<(W, IroHeader), Error> {
+    let iro_entries = iro_archive.entries().to_vec();
+    let paths = iro_entries
+        .iter()
+        .map(|iro_entry| iro_entry.path.clone())
+        .collect();
+    let layout = ArchiveLayout::new(paths, version).with_flags(iro_archive.header().flags);
+    let mut data_writer = DataWriter::new(stream, layout)?;
+    for iro_entry in &iro_entries {
+        let raw_reader = iro_archive.open_raw_entry(iro_entry)?;
+        data_writer.copy_entry(iro_entry.flags, iro_entry.data_len, raw_reader)?;
+    }
+    data_writer.finish()
+}
+
+/// Archive path in UTF-16 with the backslash separator used by FF7
+pub(crate) fn unicode_path_bytes(path: &str) -> Result<Vec<u8>, Error> {
+    let bytes: Vec<u8> = path
+        .replace('/', "\\")
+        .encode_utf16()
+        .flat_map(|ch| ch.to_le_bytes())
+        .collect();
+    if bytes.len() + INDEX_FIXED_BYTE_SIZE > u16::MAX as usize {
+        return Err(Error::PathTooLong(path.to_owned()));
+    }
+    Ok(bytes)
+}
+
+/// Layout of the header, index and data sections whose file paths are known up front, shared by
+/// every writer.
+///
+/// The index section is reserved right after the header, the data of each entry is then appended
+/// in the same order of the paths and the whole index is known once every entry is appended.
+pub(crate) struct ArchiveLayout {
+    version: IroVersion,
+    flags: IroFlags,
+    entries: Vec<IroEntry>,
+    appended_entries: usize,
+    offset: u64,
+}
+
+impl ArchiveLayout {
+    pub(crate) fn new(paths: Vec<Vec<u8>>, version: IroVersion) -> Self {
+        let index_size: u64 = paths
+            .iter()
+            .map(|path| (path.len() + version.entry_fixed_len()) as u64)
+            .sum();
+        let offset = HEADER_LEN as u64 + index_size;
+        let entries = paths
+            .into_iter()
+            .map(|path| IroEntry::new(path, FileFlags::Uncompressed, offset, 0))
+            .collect();
+        ArchiveLayout {
+            version,
+            flags: IroFlags::None,
+            entries,
+            appended_entries: 0,
+            offset,
+        }
+    }
+
+    /// Flags written in the header (default is a full archive)
+    pub(crate) fn with_flags(mut self, flags: IroFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub(crate) fn header(&self) -> IroHeader {
+        IroHeader::new(self.version, self.flags, 16, self.entries.len() as u32)
+    }
+
+    pub(crate) fn header_bytes(&self) -> Vec<u8> {
+        Vec::from(self.header())
+    }
+
+    /// Offset where the next data is appended, i.e. the end of the archive
+    pub(crate) fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Append the (already compressed) data length of the next entry
+    pub(crate) fn append_entry(
+        &mut self,
+        flags: FileFlags,
+        data_len: usize,
+    ) -> Result<&IroEntry, Error> {
+        let data_len = u32::try_from(data_len).map_err(|_| Error::FileTooLarge(data_len as u64))?;
+        if self.version == IroVersion::Zero && self.offset > u32::MAX as u64 {
+            return Err(FormatError::OffsetOverflow(self.offset, self.version as i32).into());
+        }
+
+        let entry = &mut self.entries[self.appended_entries];
+        entry.flags = flags;
+        entry.offset = self.offset;
+        entry.data_len = data_len;
+        self.appended_entries += 1;
+        self.offset += data_len as u64;
+        Ok(entry)
+    }
+
+    /// Bytes of the index section, written at [`HEADER_LEN`]
+    pub(crate) fn index_bytes(self) -> Result<Vec<u8>, Error> {
+        debug_assert_eq!(self.appended_entries, self.entries.len());
+        let mut index_bytes = Vec::new();
+        for entry in self.entries {
+            index_bytes.extend(entry.to_bytes(self.version)?);
+        }
+        Ok(index_bytes)
+    }
+}
+
+/// Buffered writer of an [`ArchiveLayout`].
+///
+/// The header is written and the index section is skipped on creation, the index is written on
+/// finish.
+struct DataWriter<W: Write + Seek> {
+    stream: BufWriter<W>,
+    layout: ArchiveLayout,
+}
+
+impl<W: Write + Seek> DataWriter<W> {
+    fn new(stream: W, layout: ArchiveLayout) -> Result<Self, Error> {
+        let mut stream = BufWriter::with_capacity(BUFFER_SIZE, stream);
+        stream.write_all(&layout.header_bytes())?;
+        stream.seek(SeekFrom::Start(layout.offset()))?;
+        Ok(DataWriter { stream, layout })
+    }
+
+    /// Append the (already compressed) data of the next entry
+    fn write_entry(&mut self, flags: FileFlags, data: &[u8]) -> Result<&IroEntry, Error> {
+        let entry = self.layout.append_entry(flags, data.len())?;
+        self.stream.write_all(data)?;
+        Ok(entry)
+    }
+
+    /// Append the (already compressed) data of the next entry read from the reader
+    fn copy_entry<R: Read>(
+        &mut self,
+        flags: FileFlags,
+        data_len: u32,
+        reader: R,
+    ) -> Result<&IroEntry, Error> {
+        let entry = self.layout.append_entry(flags, data_len as usize)?;
+        let copied_len = std::io::copy(&mut reader.take(data_len as u64), &mut self.stream)?;
+        if copied_len != data_len as u64 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(entry)
+    }
+
+    /// Append the (already compressed) data of the next entry read from the reader until its end
+    fn stream_entry<R: Read>(
+        &mut self,
+        flags: FileFlags,
+        mut reader: R,
+    ) -> Result<&IroEntry, Error> {
+        let data_len = std::io::copy(&mut reader, &mut self.stream)?;
+        self.layout.append_entry(flags, data_len as usize)
+    }
+
+    /// Write the index section and flush everything into the inner stream, returned along with
+    /// the written header
+    fn finish(mut self) -> Result<(W, IroHeader), Error> {
+        let iro_header = self.layout.header();
+        let end_offset = self.layout.offset();
+        self.stream.seek(SeekFrom::Start(HEADER_LEN as u64))?;
+        self.stream.write_all(&self.layout.index_bytes()?)?;
+        self.stream.seek(SeekFrom::Start(end_offset))?;
+        let stream = self
+            .stream
+            .into_inner()
+            .map_err(|err| Error::Io(err.into_error()))?;
+        Ok((stream, iro_header))
+    }
+}
@@ -1,21 +1,34 @@
-mod compression;
+pub mod compression;
 pub mod error;
 pub mod iro_archive;
-mod iro_entry;
-mod iro_header;
-mod iro_parser;
+#[cfg(feature = "tokio")]
+pub mod iro_async;
+pub mod iro_stream;
+pub mod iro_writer;
+pub mod patch;
+mod pipeline;
+pub mod read_at;
 
 use std::{
-    io::{BufRead, BufReader, Seek, Write},
+    collections::HashMap,
+    io::{BufReader, BufWriter, Read, Write},
     path::{Path, PathBuf},
     result::Result,
 };
 
+use compression::{Compression, CompressionRule};
 use error::Error;
-use iro_archive::IroArchive;
-use iro_entry::{FileFlags, INDEX_FIXED_BYTE_SIZE, IroEntry};
-use iro_header::{IroFlags, IroHeader, IroVersion};
-use walkdir::{DirEntry, WalkDir};
+use iro_archive::{FileFlags, IroArchive, IroEntry, IroFlags, IroHeader, IroVersion};
+use iro_stream::IroStreamReader;
+use iro_writer::{IroWriter, PackedEntry};
+use patch::{FileChange, PatchSource};
+use walkdir::WalkDir;
+
+/// Output path used to write the archive to stdout
+pub const STDOUT_PATH: &str = "-";
+
+/// IRO path used to read the archive from stdin
+pub const STDIN_PATH: &str = "-";
 
 fn glob_includes(files: &[String], entry_path: impl AsRef<[u8]>) -> bool {
     files.iter().any(|f| fast_glob::glob_match(f, &entry_path))
@@ -36,19 +49,52 @@ fn match_entry_path(
     }
 }
 
-pub fn pack_archive(
-    dir_to_pack: PathBuf,
-    output_path: Option<PathBuf>,
-    include_files: Option<Vec<String>>,
-    exclude_files: Option<Vec<String>>,
-) -> Result<PathBuf, Error> {
+/// Options used to pack a directory into an IRO archive
+#[derive(Clone, Debug, Default)]
+pub struct PackOptions {
+    /// Output file path (default is the name of the dir to pack, `-` writes to stdout)
+    pub output_path: Option<PathBuf>,
+    /// Globs of the files to include
+    pub include_files: Option<Vec<String>>,
+    /// Globs of the files to exclude
+    pub exclude_files: Option<Vec<String>>,
+    /// Compression of the files not matched by any compression rule
+    pub compression: Compression,
+    /// Compression rules by glob, the first matching rule wins
+    pub compression_rules: Vec<CompressionRule>,
+    /// Number of threads used to compress the files (default is the number of available cores)
+    pub threads: Option<usize>,
+    /// Version of the archive (default is the latest version 0x10002)
+    pub version: IroVersion,
+}
+
+impl PackOptions {
+    /// Compression of the given file path relative to the packed directory
+    pub fn compression_of(&self, relative_path: &str) -> Compression {
+        self.compression_rules
+            .iter()
+            .find(|rule| rule.matches(relative_path))
+            .map_or(self.compression, |rule| rule.compression)
+    }
+}
+
+/// Result of packing a directory into an IRO archive
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PackSummary {
+    pub output_path: PathBuf,
+    pub header: IroHeader,
+    pub entries: Vec<PackedEntry>,
+}
+
+pub fn pack_archive(dir_to_pack: PathBuf, options: PackOptions) -> Result<PackSummary, Error> {
     let dir_metadata = std::fs::metadata(&dir_to_pack)?;
     if !dir_metadata.is_dir() {
         return Err(Error::NotDir(dir_to_pack));
     }
 
     // compute output filepath: either default generated name or given output_path
-    let output_path = match output_path {
+    let output_path = match options.output_path.clone() {
         Some(path) => path,
         None => {
             let abs_path = std::fs::canonicalize(&dir_to_pack)?;
@@ -62,82 +108,262 @@ pub fn pack_archive(
     };
 
     // Do not create IRO archive if the output path already points to an existing file
-    if std::fs::File::open(&output_path).is_ok() {
+    let to_stdout = output_path == Path::new(STDOUT_PATH);
+    if !to_stdout && std::fs::File::open(&output_path).is_ok() {
         return Err(Error::OutputPathExists(output_path));
     }
 
-    let entries: Vec<DirEntry> = WalkDir::new(&dir_to_pack)
+    let files = dir_files(&dir_to_pack, &options.include_files, &options.exclude_files)?;
+
+    let (header, packed_entries) = if to_stdout {
+        let mut iro_writer = IroWriter::new(std::io::stdout().lock());
+        add_dir_entries(&mut iro_writer, &files, &options)?;
+        let (_stdout, header, packed_entries) = iro_writer.finish_streaming()?;
+        (header, packed_entries)
+    } else {
+        write_output_file(&output_path, |output_file| {
+            let mut iro_writer = IroWriter::new(output_file);
+            add_dir_entries(&mut iro_writer, &files, &options)?;
+            let (_, header, packed_entries) = iro_writer.finish()?;
+            Ok((header, packed_entries))
+        })?
+    };
+
+    Ok(PackSummary {
+        output_path,
+        header,
+        entries: packed_entries,
+    })
+}
+
+fn add_dir_entries<W: Write>(
+    iro_writer: &mut IroWriter<'_, W>,
+    files: &[(PathBuf, String)],
+    options: &PackOptions,
+) -> Result<(), Error> {
+    iro_writer.set_threads(options.threads.unwrap_or_else(pipeline::default_threads));
+    iro_writer.set_version(options.version);
+    for (file_path, relative_path) in files {
+        iro_writer.add_file(
+            relative_path,
+            file_path,
+            options.compression_of(relative_path),
+        )?;
+    }
+    Ok(())
+}
+
+/// Files inside the directory matching the filters sorted by name, along with their path
+/// relative to the directory
+fn dir_files(
+    dir: &Path,
+    include_files: &Option<Vec<String>>,
+    exclude_files: &Option<Vec<String>>,
+) -> Result<Vec<(PathBuf, String)>, Error> {
+    WalkDir::new(dir)
         .sort_by_file_name()
         .into_iter()
         .filter_map(Result::ok)
         .filter(|e| !e.file_type().is_dir())
         .filter(|e| {
-            let relative_path = e
+            match_entry_path(
+                relative_path_str(e.path(), dir),
+                include_files,
+                exclude_files,
+            )
+        })
+        .map(|entry| {
+            let relative_path = entry
                 .path()
-                .strip_prefix(dir_to_pack.as_path())
-                .unwrap()
-                .display()
-                .to_string();
-            match_entry_path(relative_path, &include_files, &exclude_files)
+                .strip_prefix(dir)?
+                .to_str()
+                .ok_or(Error::InvalidUnicode(entry.path().to_owned()))?
+                .to_owned();
+            Ok((entry.into_path(), relative_path))
         })
-        .collect();
-    let mut mod_file = std::fs::File::create(&output_path)?;
-
-    // IRO Header
-    let iro_header = IroHeader::new(IroVersion::Two, IroFlags::None, 16, entries.len() as u32);
-    let iro_header_bytes = Vec::from(iro_header);
-    let iro_header_size = iro_header_bytes.len() as u64;
-    mod_file.write_all(iro_header_bytes.as_ref())?;
-
-    let mut offset = iro_header_size;
-    for entry in &entries {
-        let unicode_filepath: Vec<u8> =
-            unicode_filepath_bytes(entry.path(), dir_to_pack.as_path())?;
-        offset += (unicode_filepath.len() + INDEX_FIXED_BYTE_SIZE) as u64;
-    }
-    mod_file.seek(std::io::SeekFrom::Start(offset))?;
-
-    let mut iro_entries: Vec<IroEntry> = Vec::with_capacity(entries.len());
-    for entry in &entries {
-        let file = std::fs::File::open(entry.to_owned().into_path())?;
-        let entry_offset = offset;
-        let mut reader = BufReader::new(file);
-        loop {
-            let bytes = reader.fill_buf()?;
-            let consumed = bytes.len();
-            if consumed == 0 {
-                break;
-            }
-            mod_file.write_all(bytes)?;
-            reader.consume(consumed);
-            offset += consumed as u64;
+        .collect()
+}
+
+/// Result of creating a patch IRO archive
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PatchSummary {
+    pub output_path: PathBuf,
+    pub header: IroHeader,
+    /// Paths of the files missing in the base
+    pub added: Vec<String>,
+    /// Paths of the files whose content differs from the base
+    pub changed: Vec<String>,
+}
+
+/// Create a patch IRO archive with only the files of `target_path` that are added or changed
+/// compared to `base_path`, both either a directory or an IRO archive.
+///
+/// Files are compared by content, entries of a target archive are copied as stored while files
+/// of a target directory are compressed following the options. The output path defaults to the
+/// name of the target followed by `-patch.iro`.
+pub fn create_patch(
+    base_path: PathBuf,
+    target_path: PathBuf,
+    options: PackOptions,
+) -> Result<PatchSummary, Error> {
+    let output_path = match options.output_path.clone() {
+        Some(path) => path,
+        None => {
+            let abs_path = std::fs::canonicalize(&target_path)?;
+            let filename = abs_path
+                .file_name()
+                .and_then(|filename| filename.to_str())
+                .ok_or(Error::CannotDetectDefaultName(abs_path.clone()))?;
+            PathBuf::from(format!("{}-patch.iro", filename.trim_end_matches(".iro")))
+        }
+    };
+    let to_stdout = output_path == Path::new(STDOUT_PATH);
+    if !to_stdout && std::fs::File::open(&output_path).is_ok() {
+        return Err(Error::OutputPathExists(output_path));
+    }
+
+    let base = PatchSource::open(&base_path, &options.include_files, &options.exclude_files)?;
+    let target = PatchSource::open(&target_path, &options.include_files, &options.exclude_files)?;
+    let threads = options.threads.unwrap_or_else(pipeline::default_threads);
+    let changed_files = patch::changed_files(&base, &target, threads)?;
+
+    let header = if to_stdout {
+        let mut iro_writer = IroWriter::new(std::io::stdout().lock());
+        add_patch_entries(&mut iro_writer, &target, &changed_files, &options)?;
+        let (_stdout, header, _) = iro_writer.finish_streaming()?;
+        header
+    } else {
+        write_output_file(&output_path, |output_file| {
+            let mut iro_writer = IroWriter::new(output_file);
+            add_patch_entries(&mut iro_writer, &target, &changed_files, &options)?;
+            let (_, header, _) = iro_writer.finish()?;
+            Ok(header)
+        })?
+    };
+
+    let (mut added, mut changed) = (Vec::new(), Vec::new());
+    for (index, change) in changed_files {
+        let path = target.path(index).to_owned();
+        match change {
+            FileChange::Added => added.push(path),
+            FileChange::Changed => changed.push(path),
         }
-        iro_entries.push(IroEntry::new(
-            unicode_filepath_bytes(entry.path(), dir_to_pack.as_path())?,
-            FileFlags::Uncompressed,
-            entry_offset,
-            (offset - entry_offset) as u32,
-        ));
     }
+    Ok(PatchSummary {
+        output_path,
+        header,
+        added,
+        changed,
+    })
+}
 
-    // indexing data
-    mod_file.seek(std::io::SeekFrom::Start(iro_header_size))?;
-    for entry in iro_entries {
-        mod_file.write_all(&Vec::from(entry))?;
+fn add_patch_entries<'a, W: Write>(
+    iro_writer: &mut IroWriter<'a, W>,
+    target: &'a PatchSource,
+    changed_files: &[(usize, FileChange)],
+    options: &PackOptions,
+) -> Result<(), Error> {
+    iro_writer.set_threads(options.threads.unwrap_or_else(pipeline::default_threads));
+    iro_writer.set_version(options.version);
+    iro_writer.set_flags(IroFlags::Patch);
+    for &(index, _) in changed_files {
+        let compression = options.compression_of(target.path(index));
+        target.add_to_writer(index, iro_writer, compression)?;
     }
+    Ok(())
+}
 
-    Ok(output_path)
+/// Apply the patch IRO archive to the base IRO archive, writing the merged archive into
+/// `output_path` (`-` writes to stdout).
+///
+/// Entries of the base are replaced or completed by the ones of the patch, all of them copied as
+/// stored. The merged archive keeps the version and the header flags of the base.
+pub fn apply_patch(
+    base_path: PathBuf,
+    patch_path: PathBuf,
+    output_path: PathBuf,
+) -> Result<PatchSummary, Error> {
+    let to_stdout = output_path == Path::new(STDOUT_PATH);
+    if !to_stdout && std::fs::File::open(&output_path).is_ok() {
+        return Err(Error::OutputPathExists(output_path));
+    }
+
+    let base = IroArchive::open(std::fs::File::open(&base_path)?)?;
+    let patch = IroArchive::open(std::fs::File::open(&patch_path)?)?;
+    // checked before creating the output file, so that nothing is left behind
+    if patch.header().flags != IroFlags::Patch {
+        return Err(Error::NotPatch(patch.header().flags));
+    }
+    let (header, patched_entries) = if to_stdout {
+        let mut iro_writer = IroWriter::new(std::io::stdout().lock());
+        iro_writer.set_version(base.header().version);
+        iro_writer.set_flags(base.header().flags);
+        let patched_entries = patch::add_patched_entries(&mut iro_writer, &base, &patch)?;
+        let (_stdout, header, _) = iro_writer.finish_streaming()?;
+        (header, patched_entries)
+    } else {
+        write_output_file(&output_path, |output_file| {
+            let mut iro_writer = IroWriter::new(output_file);
+            iro_writer.set_version(base.header().version);
+            iro_writer.set_flags(base.header().flags);
+            let patched_entries = patch::add_patched_entries(&mut iro_writer, &base, &patch)?;
+            let (_, header, _) = iro_writer.finish()?;
+            Ok((header, patched_entries))
+        })?
+    };
+
+    let (mut added, mut changed) = (Vec::new(), Vec::new());
+    for (path, change) in patched_entries {
+        match change {
+            FileChange::Added => added.push(path),
+            FileChange::Changed => changed.push(path),
+        }
+    }
+    Ok(PatchSummary {
+        output_path,
+        header,
+        added,
+        changed,
+    })
 }
 
-pub fn unpack_archive(
-    iro_path: PathBuf,
-    output_path: Option<PathBuf>,
-    include_files: Option<Vec<String>>,
-    exclude_files: Option<Vec<String>>,
-) -> Result<PathBuf, Error> {
+/// Options used to unpack an IRO archive into a directory
+#[derive(Clone, Debug, Default)]
+pub struct UnpackOptions {
+    /// Output directory path (default is the name of the IRO to unpack, required from stdin)
+    pub output_path: Option<PathBuf>,
+    /// Globs of the files to include
+    pub include_files: Option<Vec<String>>,
+    /// Globs of the files to exclude
+    pub exclude_files: Option<Vec<String>>,
+    /// Number of threads used to extract the files (default is the number of available cores)
+    pub threads: Option<usize>,
+}
+
+/// Result of unpacking an IRO archive into a directory
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct UnpackSummary {
+    pub output_path: PathBuf,
+    pub header: IroHeader,
+    /// Entries written into the output directory
+    pub entries: Vec<ListedEntry>,
+}
+
+pub fn unpack_archive(iro_path: PathBuf, options: UnpackOptions) -> Result<UnpackSummary, Error> {
+    let UnpackOptions {
+        output_path,
+        include_files,
+        exclude_files,
+        threads,
+    } = options;
+
     // compute output filepath: either default generated name or given output_path
+    let from_stdin = iro_path == Path::new(STDIN_PATH);
     let output_path = match output_path {
         Some(path) => path,
+        None if from_stdin => return Err(Error::CannotDetectDefaultName(iro_path)),
         None => {
             let filename = iro_path
                 .file_name()
@@ -152,40 +378,282 @@ pub fn unpack_archive(
         return Err(Error::OutputPathExists(output_path));
     }
 
+    if from_stdin {
+        let mut iro_stream = IroStreamReader::new(std::io::stdin().lock());
+        let iro_header = iro_stream.read_header()?;
+        let iro_entries = iro_stream.read_iro_entries(&iro_header)?;
+        let mut written_entries = Vec::new();
+        iro_stream.read_file_entries(iro_entries, |iro_entry| {
+            let entry_file =
+                create_entry_file(&output_path, iro_entry, &include_files, &exclude_files)?;
+            Ok(entry_file.map(|(iro_entry_path, entry_file)| {
+                written_entries.push(ListedEntry::new(iro_entry_path, iro_entry));
+                entry_file
+            }))
+        })?;
+        return Ok(UnpackSummary {
+            output_path,
+            header: iro_header,
+            entries: written_entries,
+        });
+    }
+
     let iro_file = std::fs::File::open(&iro_path)?;
 
-    let mut iro_archive = IroArchive::open(iro_file);
-    let iro_header = iro_archive.read_header()?;
+    let iro_archive = IroArchive::open(iro_file)?;
 
-    println!("IRO metadata");
-    println!("- version: {}", iro_header.version);
-    println!("- type: {}", iro_header.flags);
-    println!("- number of files: {}", iro_header.num_files);
-    println!();
+    // entries sharing an output path would race on the same file, so only the last one is
+    // extracted, as it is the one left by extracting them in order
+    let mut last_entries = HashMap::new();
+    for (index, iro_entry) in iro_archive.entries().iter().enumerate() {
+        let iro_entry_path = parse_utf16(&iro_entry.path)?.replace('\\', "/");
+        if match_entry_path(&iro_entry_path, &include_files, &exclude_files) {
+            last_entries.insert(iro_archive::normalize_path(&iro_entry_path), index);
+        }
+    }
+    let mut extracted_indexes: Vec<usize> = last_entries.into_values().collect();
+    extracted_indexes.sort_unstable();
 
-    let iro_entries = iro_archive.read_iro_entries(&iro_header)?;
+    // entries are extracted with positional reads on the worker threads, then reported in order
+    let mut written_entries = Vec::new();
+    pipeline::ordered_parallel_map(
+        extracted_indexes
+            .into_iter()
+            .map(|index| &iro_archive.entries()[index]),
+        threads.unwrap_or_else(pipeline::default_threads),
+        |iro_entry| {
+            let Some((iro_entry_path, entry_file)) =
+                create_entry_file(&output_path, iro_entry, &include_files, &exclude_files)?
+            else {
+                return Ok(None);
+            };
 
-    for iro_entry in iro_entries {
-        let iro_entry_path = parse_utf16(&iro_entry.path)?.replace('\\', "/");
+            let mut entry_reader = iro_archive.open_entry_at(iro_entry)?;
+            let mut entry_writer = BufWriter::new(entry_file);
+            std::io::copy(&mut entry_reader, &mut entry_writer)?;
+            entry_writer.flush()?;
+            Ok(Some(ListedEntry::new(iro_entry_path, iro_entry)))
+        },
+        |written_entry| {
+            written_entries.extend(written_entry);
+            Ok(())
+        },
+    )?;
+
+    Ok(UnpackSummary {
+        output_path,
+        header: iro_archive.header().clone(),
+        entries: written_entries,
+    })
+}
+
+/// Result of converting an IRO archive into another IRO version
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConvertSummary {
+    pub output_path: PathBuf,
+    /// Version of the original archive
+    pub from_version: IroVersion,
+    /// Header of the converted archive
+    pub header: IroHeader,
+}
+
+/// Copy the IRO archive into `output_path` using the given IRO version, entries are not
+/// compressed again.
+pub fn convert_archive(
+    iro_path: PathBuf,
+    output_path: PathBuf,
+    version: IroVersion,
+) -> Result<ConvertSummary, Error> {
+    if std::fs::File::open(&output_path).is_ok() {
+        return Err(Error::OutputPathExists(output_path));
+    }
+
+    let mut iro_archive = IroArchive::open(BufReader::new(std::fs::File::open(&iro_path)?))?;
+    let from_version = iro_archive.header().version;
+    let (_, header) = write_output_file(&output_path, |output_file| {
+        iro_writer::convert_archive(&mut iro_archive, output_file, version)
+    })?;
+    Ok(ConvertSummary {
+        output_path,
+        from_version,
+        header,
+    })
+}
+
+/// Options used to list the entries of an IRO archive
+#[derive(Clone, Debug, Default)]
+pub struct ListOptions {
+    /// Globs of the files to include
+    pub include_files: Option<Vec<String>>,
+    /// Globs of the files to exclude
+    pub exclude_files: Option<Vec<String>>,
+}
+
+/// Header and entries of a listed IRO archive
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ArchiveListing {
+    pub header: IroHeader,
+    pub entries: Vec<ListedEntry>,
+}
 
-        if !match_entry_path(&iro_entry_path, &include_files, &exclude_files) {
-            continue;
+/// Entry of a listed IRO archive
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ListedEntry {
+    /// File path inside the archive with `/` as separator
+    pub path: String,
+    pub flags: FileFlags,
+    /// Offset of the data inside the archive
+    pub offset: u64,
+    /// Size of the file stored inside the archive
+    pub stored_size: u32,
+}
+
+impl ListedEntry {
+    fn new(path: String, iro_entry: &IroEntry) -> Self {
+        ListedEntry {
+            path,
+            flags: iro_entry.flags,
+            offset: iro_entry.offset,
+            stored_size: iro_entry.data_len,
         }
+    }
+}
 
-        let entry_path = output_path.join(&iro_entry_path);
-        std::fs::create_dir_all(
-            entry_path
-                .parent()
-                .ok_or(Error::ParentPathDoesNotExist(entry_path.clone()))?,
-        )?;
-        let mut entry_file = std::fs::File::create(&entry_path).unwrap();
+/// List the entries of the IRO archive (`-` reads from stdin) matching the filters, in the same
+/// order of the index section. Only the header and the index are read.
+pub fn list_archive(iro_path: PathBuf, options: ListOptions) -> Result<ArchiveListing, Error> {
+    let mut iro_stream: Box<dyn Read> = if iro_path == Path::new(STDIN_PATH) {
+        Box::new(BufReader::new(std::io::stdin().lock()))
+    } else {
+        Box::new(BufReader::new(std::fs::File::open(&iro_path)?))
+    };
+    let header = iro_archive::read_header(&mut iro_stream)?;
+    let (iro_entries, _) = iro_archive::read_iro_entries(&mut iro_stream, &header)?;
+
+    let mut entries = Vec::with_capacity(iro_entries.len());
+    for iro_entry in iro_entries {
+        let path = parse_utf16(&iro_entry.path)?.replace('\\', "/");
+        if match_entry_path(&path, &options.include_files, &options.exclude_files) {
+            entries.push(ListedEntry::new(path, &iro_entry));
+        }
+    }
+    Ok(ArchiveListing { header, entries })
+}
+
+/// Header-level metadata of an IRO archive
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ArchiveInfo {
+    pub header: IroHeader,
+    /// Size of the index section in bytes
+    pub index_size: u64,
+    /// Size of the data section in bytes, from the end of the index to the end of the last entry
+    pub data_size: u64,
+    /// Sum of the stored size of all the entries
+    pub stored_size: u64,
+    /// Number of entries for each file flags
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_flags_counts"))]
+    pub flags_counts: Vec<(FileFlags, usize)>,
+    /// Number of bytes after the end of the last entry
+    pub trailing_bytes: u64,
+}
 
-        iro_archive.seek_and_read_file_entry(&iro_entry, &mut entry_file)?;
+/// Counts as a map keyed by the file flags, e.g. `{"uncompressed": 1, "lzss": 0, "lzma": 2}`
+#[cfg(feature = "serde")]
+fn serialize_flags_counts<S: serde::Serializer>(
+    flags_counts: &[(FileFlags, usize)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(flags_counts.iter().copied())
+}
+
+/// Read the metadata of the IRO archive (`-` reads from stdin) from its header and index, the
+/// data of the entries is not read except to measure the length of stdin.
+pub fn archive_info(iro_path: PathBuf) -> Result<ArchiveInfo, Error> {
+    let from_stdin = iro_path == Path::new(STDIN_PATH);
+    let mut iro_stream: Box<dyn Read> = if from_stdin {
+        Box::new(BufReader::new(std::io::stdin().lock()))
+    } else {
+        Box::new(BufReader::new(std::fs::File::open(&iro_path)?))
+    };
+    let header = iro_archive::read_header(&mut iro_stream)?;
+    let (iro_entries, index_size) = iro_archive::read_iro_entries(&mut iro_stream, &header)?;
+
+    let data_offset = iro_archive::HEADER_LEN as u64 + index_size;
+    let archive_len = if from_stdin {
+        data_offset + std::io::copy(&mut iro_stream, &mut std::io::sink())?
+    } else {
+        std::fs::metadata(&iro_path)?.len()
+    };
+    let data_end = iro_entries
+        .iter()
+        .map(|iro_entry| iro_entry.offset + iro_entry.data_len as u64)
+        .fold(data_offset, u64::max);
+    let flags_counts = [
+        FileFlags::Uncompressed,
+        FileFlags::LzssCompressed,
+        FileFlags::LzmaCompressed,
+    ]
+    .into_iter()
+    .map(|flags| {
+        let count = iro_entries
+            .iter()
+            .filter(|iro_entry| iro_entry.flags == flags)
+            .count();
+        (flags, count)
+    })
+    .collect();
+
+    Ok(ArchiveInfo {
+        index_size,
+        data_size: data_end - data_offset,
+        stored_size: iro_entries
+            .iter()
+            .map(|iro_entry| iro_entry.data_len as u64)
+            .sum(),
+        flags_counts,
+        trailing_bytes: archive_len.saturating_sub(data_end),
+        header,
+    })
+}
+
+/// Create the output archive and write it with `write`, removing it when writing fails so that no
+/// truncated archive is left behind (e.g. when offsets overflow version 0x10000)
+fn write_output_file<T>(
+    output_path: &Path,
+    write: impl FnOnce(std::fs::File) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let result = write(std::fs::File::create(output_path)?);
+    if result.is_err() {
+        std::fs::remove_file(output_path).ok();
+    }
+    result
+}
+
+/// Create the output file of the entry with its parent directories, unless filtered out
+fn create_entry_file(
+    output_path: &Path,
+    iro_entry: &IroEntry,
+    include_files: &Option<Vec<String>>,
+    exclude_files: &Option<Vec<String>>,
+) -> Result<Option<(String, std::fs::File)>, Error> {
+    let iro_entry_path = parse_utf16(&iro_entry.path)?.replace('\\', "/");
 
-        println!("\"{}\" file written!", iro_entry_path);
+    if !match_entry_path(&iro_entry_path, include_files, exclude_files) {
+        return Ok(None);
     }
 
-    Ok(output_path)
+    let entry_path = output_path.join(&iro_entry_path);
+    std::fs::create_dir_all(
+        entry_path
+            .parent()
+            .ok_or(Error::ParentPathDoesNotExist(entry_path.clone()))?,
+    )?;
+    let entry_file = std::fs::File::create(&entry_path)?;
+    Ok(Some((iro_entry_path, entry_file)))
 }
 
 fn parse_utf16(bytes: &[u8]) -> Result<String, Error> {
@@ -199,13 +667,9 @@ fn parse_utf16(bytes: &[u8]) -> Result<String, Error> {
         .map_err(|_| Error::InvalidUtf16("bytes in u16 cannot be converted to string".to_owned()))
 }
 
-fn unicode_filepath_bytes(path: &Path, strip_prefix_str: &Path) -> Result<Vec<u8>, Error> {
-    Ok(path
-        .strip_prefix(strip_prefix_str)?
-        .to_str()
-        .ok_or(Error::InvalidUnicode(path.to_owned()))?
-        .replace('/', "\\")
-        .encode_utf16()
-        .flat_map(|ch| ch.to_le_bytes())
-        .collect())
+fn relative_path_str(path: &Path, strip_prefix_str: &Path) -> String {
+    path.strip_prefix(strip_prefix_str)
+        .unwrap()
+        .display()
+        .to_string()
 }
@@ -1,8 +1,20 @@
-use std::{io::Write, path::PathBuf, process};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process,
+};
 
 use clap::{Args, Parser, Subcommand};
+#[cfg(feature = "serde")]
+use serde::Serialize;
 
-use iroga::{pack_archive, unpack_archive};
+use iroga::{
+    ListOptions, PackOptions, STDOUT_PATH, UnpackOptions, apply_patch, archive_info,
+    compression::{Compression, CompressionRule, LzmaPreset},
+    convert_archive, create_patch,
+    iro_archive::IroVersion,
+    list_archive, pack_archive, unpack_archive,
+};
 
 /// Command line tool to pack a single directory into a single archive in IRO format
 #[derive(Parser)]
@@ -18,6 +30,32 @@ enum Commands {
     Pack(PackArgs),
     /// Unpack a IRO archive into a directory
     Unpack(UnpackArgs),
+    /// List the files inside a IRO archive
+    List(ListArgs),
+    /// Show the metadata of a IRO archive
+    Info(InfoArgs),
+    /// Convert a IRO archive into another IRO version
+    Convert(ConvertArgs),
+    /// Create and apply patch IRO archives
+    #[command(subcommand)]
+    Patch(PatchCommands),
+}
+
+#[derive(Subcommand)]
+enum PatchCommands {
+    /// Create a patch IRO archive with the files added or changed from a base to a target
+    Create(PatchCreateArgs),
+    /// Apply a patch IRO archive to a base IRO archive, replacing or adding its files
+    Apply(PatchApplyArgs),
+}
+
+/// Format of the printed result
+#[cfg(feature = "serde")]
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+enum OutputFormat {
+    Text,
+    /// A single object, either `{"result": ...}` or `{"error": "..."}`
+    Json,
 }
 
 #[derive(Args)]
@@ -26,7 +64,7 @@ struct PackArgs {
     #[arg()]
     dir: PathBuf,
 
-    /// Output file path (default is the name of the dir to pack)
+    /// Output file path (default is the name of the dir to pack, "-" writes to stdout)
     #[arg(short, long)]
     output: Option<PathBuf>,
 
@@ -37,11 +75,62 @@ struct PackArgs {
     /// Files to exclude
     #[arg(short, long)]
     exclude: Option<Vec<String>>,
+
+    #[command(flatten)]
+    compression: CompressionArgs,
+
+    /// Number of threads used to compress the files (default is the number of available cores)
+    #[arg(short, long)]
+    threads: Option<usize>,
+
+    /// IRO version of the archive (0x10000, 0x10001, 0x10002)
+    #[arg(long, default_value = "0x10002", value_parser = parse_iro_version)]
+    format_version: IroVersion,
+
+    /// Output format (text, json)
+    #[cfg(feature = "serde")]
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(Args)]
+struct CompressionArgs {
+    /// Compression of the packed files (none, lzss, lzma, lzma2, auto)
+    #[arg(short, long, default_value_t = Compression::None)]
+    compression: Compression,
+
+    /// Compression of the files matching a glob, in the form <GLOB>=<COMPRESSION> (e.g. '*.ogg=none')
+    #[arg(long = "compress-rule", value_name = "RULE")]
+    compress_rules: Vec<CompressionRule>,
+
+    /// LZMA/LZMA2 compression level from 0 (fastest) to 9 (smallest)
+    #[arg(long, default_value_t = LzmaPreset::DEFAULT_LEVEL, value_parser = clap::value_parser!(u32).range(0..=9))]
+    lzma_level: u32,
+
+    /// LZMA/LZMA2 dictionary size in bytes (default depends on the level)
+    #[arg(long)]
+    lzma_dict_size: Option<u32>,
+}
+
+impl CompressionArgs {
+    /// Default compression and compression rules with the LZMA preset applied
+    fn into_compressions(self) -> (Compression, Vec<CompressionRule>) {
+        let lzma_preset = LzmaPreset::new(self.lzma_level, self.lzma_dict_size);
+        let compression_rules = self
+            .compress_rules
+            .into_iter()
+            .map(|rule| rule.with_lzma_preset(lzma_preset))
+            .collect();
+        (
+            self.compression.with_lzma_preset(lzma_preset),
+            compression_rules,
+        )
+    }
 }
 
 #[derive(Args)]
 struct UnpackArgs {
-    /// IRO file to unpack
+    /// IRO file to unpack ("-" reads from stdin)
     #[arg()]
     iro_path: PathBuf,
 
@@ -56,18 +145,210 @@ struct UnpackArgs {
     /// Files to exclude
     #[arg(short, long)]
     exclude: Option<Vec<String>>,
+
+    /// Number of threads used to extract the files (default is the number of available cores)
+    #[arg(short, long)]
+    threads: Option<usize>,
+
+    /// Output format (text, json)
+    #[cfg(feature = "serde")]
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(Args)]
+struct ListArgs {
+    /// IRO file to list ("-" reads from stdin)
+    #[arg()]
+    iro_path: PathBuf,
+
+    /// Long format with the compression, offset and stored size of each file
+    #[arg(short, long)]
+    long: bool,
+
+    /// Files to include
+    #[arg(short, long)]
+    include: Option<Vec<String>>,
+
+    /// Files to exclude
+    #[arg(short, long)]
+    exclude: Option<Vec<String>>,
+
+    /// Output format (text, json)
+    #[cfg(feature = "serde")]
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(Args)]
+struct InfoArgs {
+    /// IRO file to inspect ("-" reads from stdin)
+    #[arg()]
+    iro_path: PathBuf,
+
+    /// Output format (text, json)
+    #[cfg(feature = "serde")]
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(Args)]
+struct ConvertArgs {
+    /// IRO file to convert
+    #[arg()]
+    iro_path: PathBuf,
+
+    /// Output file path of the converted archive
+    #[arg()]
+    output: PathBuf,
+
+    /// IRO version of the converted archive (0x10000, 0x10001, 0x10002)
+    #[arg(long, value_parser = parse_iro_version)]
+    to_version: IroVersion,
+
+    /// Output format (text, json)
+    #[cfg(feature = "serde")]
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(Args)]
+struct PatchCreateArgs {
+    /// Base directory or IRO file
+    #[arg(long)]
+    base: PathBuf,
+
+    /// Target directory or IRO file whose added and changed files are put inside the patch
+    #[arg(long)]
+    target: PathBuf,
+
+    /// Output file path (default is the name of the target followed by "-patch.iro", "-" writes to stdout)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Files to include
+    #[arg(short, long)]
+    include: Option<Vec<String>>,
+
+    /// Files to exclude
+    #[arg(short, long)]
+    exclude: Option<Vec<String>>,
+
+    // entries of a target IRO are kept as they are stored, only files of a directory are compressed
+    #[command(flatten)]
+    compression: CompressionArgs,
+
+    /// Number of threads used to compare and compress the files (default is the number of available cores)
+    #[arg(short, long)]
+    threads: Option<usize>,
+
+    /// IRO version of the patch (0x10000, 0x10001, 0x10002)
+    #[arg(long, default_value = "0x10002", value_parser = parse_iro_version)]
+    format_version: IroVersion,
+
+    /// Output format (text, json)
+    #[cfg(feature = "serde")]
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(Args)]
+struct PatchApplyArgs {
+    /// Base IRO file
+    #[arg()]
+    base: PathBuf,
+
+    /// Patch IRO file
+    #[arg()]
+    patch: PathBuf,
+
+    /// Output file path of the merged archive ("-" writes to stdout)
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Output format (text, json)
+    #[cfg(feature = "serde")]
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+fn parse_iro_version(value: &str) -> Result<IroVersion, String> {
+    let hex_value = value.strip_prefix("0x").unwrap_or(value);
+    i32::from_str_radix(hex_value, 16)
+        .ok()
+        .and_then(|version| IroVersion::try_from(version).ok())
+        .ok_or_else(|| "expected 0x10000, 0x10001 or 0x10002".to_owned())
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum JsonOutput<T> {
+    Result(T),
+    Error(String),
+}
+
+/// Print the result as a JSON object and exit with the matching code
+#[cfg(feature = "serde")]
+fn exit_with_json<T: Serialize>(mut out: impl Write, result: Result<T, iroga::error::Error>) -> ! {
+    let (output, code) = match result {
+        Ok(result) => (JsonOutput::Result(result), 0),
+        Err(err) => (JsonOutput::Error(err.to_string()), 1),
+    };
+    serde_json::to_writer(&mut out, &output).ok();
+    writeln!(out).ok();
+    process::exit(code);
+}
+
+/// Output of the messages about a written archive, stderr when the archive itself is written to
+/// stdout
+fn summary_output(output_path: &Path) -> Box<dyn Write> {
+    if output_path == Path::new(STDOUT_PATH) {
+        Box::new(std::io::stderr().lock())
+    } else {
+        Box::new(std::io::stdout().lock())
+    }
 }
 
 fn main() {
     let cli = Cli::parse();
     match cli.command {
         Commands::Pack(args) => {
-            match pack_archive(args.dir, args.output, args.include, args.exclude) {
-                Ok(output_filename) => {
-                    println!(
+            let (compression, compression_rules) = args.compression.into_compressions();
+            let options = PackOptions {
+                output_path: args.output,
+                include_files: args.include,
+                exclude_files: args.exclude,
+                compression,
+                compression_rules,
+                threads: args.threads,
+                version: args.format_version,
+            };
+            #[cfg(feature = "serde")]
+            if args.format == OutputFormat::Json {
+                let output_path = options.output_path.clone().unwrap_or_default();
+                exit_with_json(
+                    summary_output(&output_path),
+                    pack_archive(args.dir, options),
+                );
+            }
+            match pack_archive(args.dir, options) {
+                Ok(summary) => {
+                    let mut out = summary_output(&summary.output_path);
+                    for entry in summary.entries {
+                        writeln!(
+                            out,
+                            "\"{}\" file packed ({}, {} -> {} bytes)",
+                            entry.path, entry.flags, entry.size, entry.stored_size
+                        )
+                        .ok();
+                    }
+                    writeln!(
+                        out,
                         "archive \"{}\" has been created!",
-                        output_filename.display()
-                    );
+                        summary.output_path.display()
+                    )
+                    .ok();
                     process::exit(0);
                 }
                 Err(err) => {
@@ -78,9 +359,182 @@ fn main() {
             }
         }
         Commands::Unpack(args) => {
-            match unpack_archive(args.iro_path, args.output, args.include, args.exclude) {
-                Ok(output_dir) => {
-                    println!("IRO unpacked into \"{}\" directory", output_dir.display());
+            let options = UnpackOptions {
+                output_path: args.output,
+                include_files: args.include,
+                exclude_files: args.exclude,
+                threads: args.threads,
+            };
+            let result = unpack_archive(args.iro_path, options);
+            #[cfg(feature = "serde")]
+            if args.format == OutputFormat::Json {
+                exit_with_json(std::io::stdout().lock(), result);
+            }
+            match result {
+                Ok(summary) => {
+                    for entry in summary.entries {
+                        println!("\"{}\" file written!", entry.path);
+                    }
+                    println!(
+                        "IRO unpacked into \"{}\" directory",
+                        summary.output_path.display()
+                    );
+                    process::exit(0);
+                }
+                Err(err) => {
+                    let stderr = std::io::stderr();
+                    writeln!(stderr.lock(), "[iroga error]: {}", err).ok();
+                    process::exit(1);
+                }
+            }
+        }
+        Commands::List(args) => {
+            let options = ListOptions {
+                include_files: args.include,
+                exclude_files: args.exclude,
+            };
+            let result = list_archive(args.iro_path, options);
+            #[cfg(feature = "serde")]
+            if args.format == OutputFormat::Json {
+                exit_with_json(std::io::stdout().lock(), result);
+            }
+            match result {
+                Ok(listing) => {
+                    let mut out = std::io::stdout().lock();
+                    for entry in listing.entries {
+                        if args.long {
+                            writeln!(
+                                out,
+                                "{:<12} {:>12} {:>10} {}",
+                                entry.flags, entry.offset, entry.stored_size, entry.path
+                            )
+                            .ok();
+                        } else {
+                            writeln!(out, "{}", entry.path).ok();
+                        }
+                    }
+                    process::exit(0);
+                }
+                Err(err) => {
+                    let stderr = std::io::stderr();
+                    writeln!(stderr.lock(), "[iroga error]: {}", err).ok();
+                    process::exit(1);
+                }
+            }
+        }
+        Commands::Info(args) => {
+            let result = archive_info(args.iro_path);
+            #[cfg(feature = "serde")]
+            if args.format == OutputFormat::Json {
+                exit_with_json(std::io::stdout().lock(), result);
+            }
+            match result {
+                Ok(info) => {
+                    println!("IRO metadata");
+                    println!("- version: {}", info.header.version);
+                    println!("- type: {}", info.header.flags);
+                    println!("- number of files: {}", info.header.num_files);
+                    println!("- index size: {} bytes", info.index_size);
+                    println!("- data size: {} bytes", info.data_size);
+                    println!("- stored size: {} bytes", info.stored_size);
+                    for (flags, count) in info.flags_counts {
+                        println!("- {} files: {}", flags, count);
+                    }
+                    println!("- trailing bytes: {}", info.trailing_bytes);
+                    process::exit(0);
+                }
+                Err(err) => {
+                    let stderr = std::io::stderr();
+                    writeln!(stderr.lock(), "[iroga error]: {}", err).ok();
+                    process::exit(1);
+                }
+            }
+        }
+        Commands::Convert(args) => {
+            let result = convert_archive(args.iro_path, args.output, args.to_version);
+            #[cfg(feature = "serde")]
+            if args.format == OutputFormat::Json {
+                exit_with_json(std::io::stdout().lock(), result);
+            }
+            match result {
+                Ok(summary) => {
+                    println!(
+                        "archive \"{}\" has been converted from {} to {}!",
+                        summary.output_path.display(),
+                        summary.from_version,
+                        summary.header.version
+                    );
+                    process::exit(0);
+                }
+                Err(err) => {
+                    let stderr = std::io::stderr();
+                    writeln!(stderr.lock(), "[iroga error]: {}", err).ok();
+                    process::exit(1);
+                }
+            }
+        }
+        Commands::Patch(PatchCommands::Create(args)) => {
+            let (compression, compression_rules) = args.compression.into_compressions();
+            let options = PackOptions {
+                output_path: args.output,
+                include_files: args.include,
+                exclude_files: args.exclude,
+                compression,
+                compression_rules,
+                threads: args.threads,
+                version: args.format_version,
+            };
+            #[cfg(feature = "serde")]
+            if args.format == OutputFormat::Json {
+                let output_path = options.output_path.clone().unwrap_or_default();
+                let result = create_patch(args.base, args.target, options);
+                exit_with_json(summary_output(&output_path), result);
+            }
+            match create_patch(args.base, args.target, options) {
+                Ok(summary) => {
+                    let mut out = summary_output(&summary.output_path);
+                    for path in summary.added {
+                        writeln!(out, "\"{}\" file added", path).ok();
+                    }
+                    for path in summary.changed {
+                        writeln!(out, "\"{}\" file changed", path).ok();
+                    }
+                    writeln!(
+                        out,
+                        "patch \"{}\" has been created!",
+                        summary.output_path.display()
+                    )
+                    .ok();
+                    process::exit(0);
+                }
+                Err(err) => {
+                    let stderr = std::io::stderr();
+                    writeln!(stderr.lock(), "[iroga error]: {}", err).ok();
+                    process::exit(1);
+                }
+            }
+        }
+        Commands::Patch(PatchCommands::Apply(args)) => {
+            #[cfg(feature = "serde")]
+            if args.format == OutputFormat::Json {
+                let out = summary_output(&args.output);
+                exit_with_json(out, apply_patch(args.base, args.patch, args.output));
+            }
+            match apply_patch(args.base, args.patch, args.output) {
+                Ok(summary) => {
+                    let mut out = summary_output(&summary.output_path);
+                    for path in summary.added {
+                        writeln!(out, "\"{}\" file added", path).ok();
+                    }
+                    for path in summary.changed {
+                        writeln!(out, "\"{}\" file replaced", path).ok();
+                    }
+                    writeln!(
+                        out,
+                        "archive \"{}\" has been patched!",
+                        summary.output_path.display()
+                    )
+                    .ok();
                     process::exit(0);
                 }
                 Err(err) => {
@@ -0,0 +1,219 @@
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    fs::File,
+    io::{BufRead, BufReader, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::Error;
+use crate::compression::Compression;
+use crate::iro_archive::{IroArchive, IroEntry, IroFlags, normalize_path};
+use crate::iro_writer::IroWriter;
+use crate::read_at::ReadAt;
+use crate::{dir_files, match_entry_path, parse_utf16, pipeline};
+
+/// Files of one side of a patch, either a directory or an IRO archive
+pub(crate) enum PatchSource {
+    Dir(Vec<(PathBuf, String)>),
+    Archive(IroArchive<File>, Vec<(IroEntry, String)>),
+}
+
+impl PatchSource {
+    /// Open the directory or the IRO archive at `path`, keeping only the files matching the
+    /// filters
+    pub(crate) fn open(
+        path: &Path,
+        include_files: &Option<Vec<String>>,
+        exclude_files: &Option<Vec<String>>,
+    ) -> Result<Self, Error> {
+        if std::fs::metadata(path)?.is_dir() {
+            return Ok(PatchSource::Dir(dir_files(
+                path,
+                include_files,
+                exclude_files,
+            )?));
+        }
+
+        let iro_archive = IroArchive::open(File::open(path)?)?;
+        let mut entries = Vec::with_capacity(iro_archive.entries().len());
+        for iro_entry in iro_archive.entries() {
+            let entry_path = parse_utf16(&iro_entry.path)?.replace('\\', "/");
+            if match_entry_path(&entry_path, include_files, exclude_files) {
+                entries.push((iro_entry.clone(), entry_path));
+            }
+        }
+        Ok(PatchSource::Archive(iro_archive, entries))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            PatchSource::Dir(files) => files.len(),
+            PatchSource::Archive(_, entries) => entries.len(),
+        }
+    }
+
+    /// Path of the file with `/` as separator
+    pub(crate) fn path(&self, index: usize) -> &str {
+        match self {
+            PatchSource::Dir(files) => &files[index].1,
+            PatchSource::Archive(_, entries) => &entries[index].1,
+        }
+    }
+
+    /// Decompressed content of the file
+    fn open_file(&self, index: usize) -> Result<Box<dyn Read + '_>, Error> {
+        match self {
+            PatchSource::Dir(files) => Ok(Box::new(File::open(&files[index].0)?)),
+            PatchSource::Archive(iro_archive, entries) => {
+                Ok(Box::new(iro_archive.open_entry_at(&entries[index].0)?))
+            }
+        }
+    }
+
+    /// Add the file to the writer, entries of an archive are copied as stored without compressing
+    /// them again
+    pub(crate) fn add_to_writer<'a, W: Write>(
+        &'a self,
+        index: usize,
+        iro_writer: &mut IroWriter<'a, W>,
+        compression: Compression,
+    ) -> Result<(), Error> {
+        match self {
+            PatchSource::Dir(files) => {
+                let (file_path, path) = &files[index];
+                iro_writer.add_file(path, file_path, compression)
+            }
+            PatchSource::Archive(iro_archive, entries) => {
+                let (iro_entry, path) = &entries[index];
+                let reader = iro_archive.open_raw_entry_at(iro_entry);
+                iro_writer.add_stored_reader(path, reader, iro_entry.flags)
+            }
+        }
+    }
+}
+
+/// Change of a patched file compared to the base
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileChange {
+    Added,
+    Changed,
+}
+
+/// Files of `target` missing in `base` or whose content differs from the base file with the same
+/// path (compared case-insensitively), in the same order of the target files
+pub(crate) fn changed_files(
+    base: &PatchSource,
+    target: &PatchSource,
+    threads: usize,
+) -> Result<Vec<(usize, FileChange)>, Error> {
+    let base_index: HashMap<String, usize> = (0..base.len())
+        .map(|index| (normalize_path(base.path(index)), index))
+        .collect();
+
+    let mut changed_files = Vec::new();
+    pipeline::ordered_parallel_map(
+        0..target.len(),
+        threads,
+        |index| {
+            let change = match base_index.get(&normalize_path(target.path(index))) {
+                None => Some(FileChange::Added),
+                Some(&base_index) => {
+                    let base_reader = BufReader::new(base.open_file(base_index)?);
+                    let target_reader = BufReader::new(target.open_file(index)?);
+                    if same_content(base_reader, target_reader)? {
+                        None
+                    } else {
+                        Some(FileChange::Changed)
+                    }
+                }
+            };
+            Ok(change.map(|change| (index, change)))
+        },
+        |changed_file| {
+            changed_files.extend(changed_file);
+            Ok(())
+        },
+    )?;
+    Ok(changed_files)
+}
+
+/// Add the entries of the `base` archive to the writer, replacing the ones with the same path
+/// (compared case-insensitively) by the entries of the `patch` archive, which are then added after
+/// the base entries when new. Returns the path of the patched entries with their change.
+///
+/// Entries are added as stored without compressing them again, the header flags of `patch` must
+/// be [`IroFlags::Patch`]. When several patch entries share a path, only the first one is used,
+/// the same returned by [`IroArchive::by_path`].
+pub fn add_patched_entries<'a, R, P, W>(
+    iro_writer: &mut IroWriter<'a, W>,
+    base: &'a IroArchive<R>,
+    patch: &'a IroArchive<P>,
+) -> Result<Vec<(String, FileChange)>, Error>
+where
+    R: Read + Seek + ReadAt + Sync,
+    P: Read + Seek + ReadAt + Sync,
+    W: Write,
+{
+    if patch.header().flags != IroFlags::Patch {
+        return Err(Error::NotPatch(patch.header().flags));
+    }
+
+    // patch entries left to add after the base entries, i.e. neither replacing a base entry nor
+    // shadowed by a previous patch entry with the same path
+    let mut added = vec![true; patch.entries().len()];
+    let mut patch_entries = HashMap::with_capacity(patch.entries().len());
+    for (index, iro_entry) in patch.entries().iter().enumerate() {
+        match patch_entries.entry(normalize_path(&parse_utf16(&iro_entry.path)?)) {
+            Entry::Occupied(_) => added[index] = false,
+            Entry::Vacant(entry) => {
+                entry.insert(index);
+            }
+        }
+    }
+
+    let mut patched_entries = Vec::new();
+    for iro_entry in base.entries() {
+        let path = parse_utf16(&iro_entry.path)?;
+        match patch_entries.get(&normalize_path(&path)) {
+            Some(&index) => {
+                let patch_entry = &patch.entries()[index];
+                let reader = patch.open_raw_entry_at(patch_entry);
+                iro_writer.add_stored_reader(&path, reader, patch_entry.flags)?;
+                patched_entries.push((path.replace('\\', "/"), FileChange::Changed));
+                added[index] = false;
+            }
+            None => {
+                let reader = base.open_raw_entry_at(iro_entry);
+                iro_writer.add_stored_reader(&path, reader, iro_entry.flags)?;
+            }
+        }
+    }
+    for (patch_entry, _) in patch
+        .entries()
+        .iter()
+        .zip(added)
+        .filter(|(_, added)| *added)
+    {
+        let path = parse_utf16(&patch_entry.path)?;
+        let reader = patch.open_raw_entry_at(patch_entry);
+        iro_writer.add_stored_reader(&path, reader, patch_entry.flags)?;
+        patched_entries.push((path.replace('\\', "/"), FileChange::Added));
+    }
+    Ok(patched_entries)
+}
+
+fn same_content(mut left: impl BufRead, mut right: impl BufRead) -> std::io::Result<bool> {
+    loop {
+        let left_buf = left.fill_buf()?;
+        let right_buf = right.fill_buf()?;
+        if left_buf.is_empty() || right_buf.is_empty() {
+            return Ok(left_buf.is_empty() && right_buf.is_empty());
+        }
+        let len = left_buf.len().min(right_buf.len());
+        if left_buf[..len] != right_buf[..len] {
+            return Ok(false);
+        }
+        left.consume(len);
+        right.consume(len);
+    }
+}
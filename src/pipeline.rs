@@ -0,0 +1,100 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        Mutex,
+        mpsc::{self, Receiver, SyncSender},
+    },
+};
+
+use crate::Error;
+
+type Job<T, U> = (T, SyncSender<Result<U, Error>>);
+
+/// Number of threads to use when not given: all the available cores
+pub fn default_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |threads| threads.get())
+}
+
+/// Run `job` on every item using a pool of `threads` workers and pass the results to `consume`
+/// in the same order of the items.
+///
+/// At most `2 * threads` items are scheduled ahead of the one being consumed, so memory use stays
+/// bounded regardless of the number of items.
+pub fn ordered_parallel_map<T, U, F, C>(
+    items: impl IntoIterator<Item = T>,
+    threads: usize,
+    job: F,
+    mut consume: C,
+) -> Result<(), Error>
+where
+    T: Send,
+    U: Send,
+    F: Fn(T) -> Result<U, Error> + Sync,
+    C: FnMut(U) -> Result<(), Error>,
+{
+    if threads <= 1 {
+        for item in items {
+            consume(job(item)?)?;
+        }
+        return Ok(());
+    }
+
+    let (job_sender, job_receiver) = mpsc::channel::<Job<T, U>>();
+    let job_receiver = Mutex::new(job_receiver);
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| worker(&job_receiver, &job));
+        }
+
+        let max_pending = threads * 2;
+        let mut pending: VecDeque<Receiver<Result<U, Error>>> = VecDeque::new();
+        for item in items {
+            let (result_sender, result_receiver) = mpsc::sync_channel(1);
+            job_sender
+                .send((item, result_sender))
+                .expect("workers stop only when the job sender is dropped");
+            pending.push_back(result_receiver);
+            if pending.len() >= max_pending {
+                consume_next(&mut pending, &mut consume)?;
+            }
+        }
+        drop(job_sender);
+        while !pending.is_empty() {
+            consume_next(&mut pending, &mut consume)?;
+        }
+        Ok(())
+    })
+}
+
+fn worker<T, U, F>(job_receiver: &Mutex<Receiver<Job<T, U>>>, job: &F)
+where
+    F: Fn(T) -> Result<U, Error>,
+{
+    loop {
+        let next_job = job_receiver
+            .lock()
+            .expect("job receiver lock is never poisoned")
+            .recv();
+        match next_job {
+            // the result is not needed anymore when the receiver has been dropped after a failure
+            Ok((item, result_sender)) => {
+                let _ = result_sender.send(job(item));
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+fn consume_next<U, C>(
+    pending: &mut VecDeque<Receiver<Result<U, Error>>>,
+    consume: &mut C,
+) -> Result<(), Error>
+where
+    C: FnMut(U) -> Result<(), Error>,
+{
+    let result_receiver = pending.pop_front().expect("pending results are not empty");
+    let result = result_receiver
+        .recv()
+        .expect("workers always send the result of a scheduled job");
+    consume(result?)
+}
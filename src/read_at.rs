@@ -0,0 +1,102 @@
+use std::{
+    fs::File,
+    io::{Cursor, Read, Seek, SeekFrom},
+};
+
+/// Source of bytes read at a given offset without a shared cursor, so that many threads can read
+/// from it at once (e.g. a `File` through positional reads)
+pub trait ReadAt {
+    /// Read bytes starting from `offset` into `buf`, returning how many bytes were read
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize>;
+}
+
+impl ReadAt for File {
+    #[cfg(unix)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        std::os::unix::fs::FileExt::read_at(self, buf, offset)
+    }
+
+    #[cfg(windows)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+    }
+
+    /// Without positional reads, the cursor shared by every handle of the file is moved, so a
+    /// single read runs at once
+    #[cfg(not(any(unix, windows)))]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        static CURSOR_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        let _guard = CURSOR_LOCK
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let mut file = self;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read(buf)
+    }
+}
+
+impl ReadAt for [u8] {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        let start = (offset as usize).min(self.len());
+        let len = buf.len().min(self.len() - start);
+        buf[..len].copy_from_slice(&self[start..start + len]);
+        Ok(len)
+    }
+}
+
+impl ReadAt for Vec<u8> {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        self.as_slice().read_at(buf, offset)
+    }
+}
+
+/// Reads the inner bytes regardless of the cursor position, e.g. for in-memory archives
+impl<T: AsRef<[u8]>> ReadAt for Cursor<T> {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        self.get_ref().as_ref().read_at(buf, offset)
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        (**self).read_at(buf, offset)
+    }
+}
+
+/// Cursor over a [`ReadAt`] source, each cursor keeps its own position
+pub struct ReadAtCursor<'a, R: ?Sized> {
+    source: &'a R,
+    position: u64,
+}
+
+impl<'a, R: ReadAt + ?Sized> ReadAtCursor<'a, R> {
+    pub fn new(source: &'a R, position: u64) -> Self {
+        ReadAtCursor { source, position }
+    }
+}
+
+impl<R: ReadAt + ?Sized> Read for ReadAtCursor<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.source.read_at(buf, self.position)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: ReadAt + ?Sized> Seek for ReadAtCursor<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            // the end is not known, entries are always seeked from their start
+            SeekFrom::End(_) => None,
+        }
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek position of positional reader",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
@@ -3,8 +3,17 @@ use assert_fs::{
     assert::PathAssert,
     fixture::{FileTouch, FileWriteBin, FileWriteStr, PathChild},
 };
-use predicates::prelude::predicate;
+use predicates::prelude::{PredicateBooleanExt, predicate};
 use hex_literal::hex;
+use iroga::{
+    compression::{Compression, LzmaPreset},
+    iro_archive::{FileFlags, IroArchive, IroEntryRef, IroEntryRefs, IroFlags},
+    iro_writer::IroWriter,
+};
+use std::{
+    borrow::Cow,
+    io::{Cursor, Read, Seek, SeekFrom},
+};
 
 #[test]
 pub fn pack_not_exists_file() {
@@ -75,163 +84,1912 @@ pub fn pack_single_file() {
 }
 
 #[test]
-pub fn pack_multiple_files() {
+pub fn pack_single_file_version_zero() {
     const EXPECTED_BYTES: &[u8] = &hex!(
-        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
-        "03 00 00 00 1e 00 0a 00   61 00 2e 00 74 00 78 00"
-        "74 00 00 00 00 00 76 00   00 00 00 00 00 00 01 00"
-        "00 00 1e 00 0a 00 62 00   2e 00 74 00 78 00 74 00"
-        "00 00 00 00 77 00 00 00   00 00 00 00 01 00 00 00"
-        "26 00 12 00 64 00 69 00   72 00 5c 00 63 00 2e 00"
-        "74 00 78 00 74 00 00 00   00 00 78 00 00 00 00 00"
-        "00 00 01 00 00 00 41 42   43                     "
+        "49 52 4f 53 00 00 01 00   00 00 00 00 10 00 00 00"
+        "01 00 00 00 20 00 10 00   66 00 69 00 6c 00 65 00"
+        "2e 00 74 00 78 00 74 00   00 00 00 00 34 00 00 00"
+        "17 00 00 00 48 65 6c 6c   6f 20 57 6f 72 6c 64 21"
+        "0d 0a 0d 0a 48 69 21 0d   0a 0d 0a               "
     );
     let dir = assert_fs::TempDir::new().unwrap();
-    dir.child("multiple/a.txt").write_str("A").unwrap();
-    dir.child("multiple/b.txt").write_str("B").unwrap();
-    dir.child("multiple/dir/c.txt").write_str("C").unwrap();
+    dir.child("single/file.txt")
+        .write_str("Hello World!\r\n\r\nHi!\r\n\r\n")
+        .unwrap();
 
     iroga_cmd()
         .current_dir(dir.path())
         .arg("pack")
-        .arg(dir.path().join("multiple"))
+        .arg("--format-version")
+        .arg("0x10000")
+        .arg(dir.path().join("single"))
         .assert()
         .success()
         .code(0);
 
-    assert!(dir.child("multiple.iro").exists());
-    dir.child("multiple.iro").assert(EXPECTED_BYTES);
+    dir.child("single.iro").assert(EXPECTED_BYTES);
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg("single.iro")
+        .arg("--output")
+        .arg("unpacked")
+        .assert()
+        .success()
+        .code(0);
+    dir.child("unpacked/file.txt")
+        .assert("Hello World!\r\n\r\nHi!\r\n\r\n");
     dir.close().unwrap();
 }
 
 #[test]
-pub fn pack_specific_files() {
-    const EXPECTED_BYTES: &[u8] = &hex!(
-        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
-        "02 00 00 00 1e 00 0a 00   62 00 2e 00 74 00 78 00"
-        "74 00 00 00 00 00 58 00   00 00 00 00 00 00 01 00"
-        "00 00 26 00 12 00 64 00   69 00 72 00 5c 00 63 00"
-        "2e 00 74 00 78 00 74 00   00 00 00 00 59 00 00 00"
-        "00 00 00 00 01 00 00 00   42 43                  "
-    );
+pub fn pack_invalid_format_version() {
     let dir = assert_fs::TempDir::new().unwrap();
-    dir.child("specific/a.txt").write_str("A").unwrap();
-    dir.child("specific/b.txt").write_str("B").unwrap();
-    dir.child("specific/dir/c.txt").write_str("C").unwrap();
+    dir.child("single/file.txt").touch().unwrap();
 
     iroga_cmd()
         .current_dir(dir.path())
         .arg("pack")
-        .arg("--include")
-        .arg("b.txt")
-        .arg("--include")
-        .arg("*/c.txt")
-        .arg(dir.path().join("specific"))
+        .arg("--format-version")
+        .arg("0x10003")
+        .arg(dir.path().join("single"))
         .assert()
-        .success()
-        .code(0);
-
-    assert!(dir.child("specific.iro").exists());
-    dir.child("specific.iro").assert(EXPECTED_BYTES);
+        .failure()
+        .stderr(predicates::str::contains("expected 0x10000, 0x10001 or 0x10002"));
+    dir.child("single.iro").assert(predicate::path::missing());
     dir.close().unwrap();
 }
 
 #[test]
-pub fn unpack_not_exists_file() {
+pub fn convert_between_versions() {
+    const V0_BYTES: &[u8] = &hex!(
+        "49 52 4f 53 00 00 01 00   00 00 00 00 10 00 00 00"
+        "01 00 00 00 20 00 10 00   66 00 69 00 6c 00 65 00"
+        "2e 00 74 00 78 00 74 00   00 00 00 00 34 00 00 00"
+        "17 00 00 00 48 65 6c 6c   6f 20 57 6f 72 6c 64 21"
+        "0d 0a 0d 0a 48 69 21 0d   0a 0d 0a               "
+    );
+    const V2_BYTES: &[u8] = &hex!(
+        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
+        "01 00 00 00 24 00 10 00   66 00 69 00 6c 00 65 00"
+        "2e 00 74 00 78 00 74 00   00 00 00 00 38 00 00 00"
+        "00 00 00 00 17 00 00 00   48 65 6c 6c 6f 20 57 6f"
+        "72 6c 64 21 0d 0a 0d 0a   48 69 21 0d 0a 0d 0a   "
+    );
     let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("v0.iro").write_binary(V0_BYTES).unwrap();
+
     iroga_cmd()
         .current_dir(dir.path())
-        .arg("unpack")
-        .arg(dir.path().join("not_exists_file.iro"))
+        .arg("convert")
+        .arg("--to-version")
+        .arg("0x10002")
+        .arg("v0.iro")
+        .arg("v2.iro")
         .assert()
-        .failure()
-        .code(1);
-    assert!(!dir.child("not_exists_file.iro").exists());
+        .success()
+        .code(0)
+        .stdout(predicates::str::contains("converted from 0x10000 to 0x10002"));
+    dir.child("v2.iro").assert(V2_BYTES);
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("convert")
+        .arg("--to-version")
+        .arg("0x10000")
+        .arg("v2.iro")
+        .arg("v0_again.iro")
+        .assert()
+        .success()
+        .code(0);
+    dir.child("v0_again.iro").assert(V0_BYTES);
+    dir.close().unwrap();
 }
 
 #[test]
-pub fn unpack_not_file() {
+pub fn convert_keeps_compressed_data() {
     let dir = assert_fs::TempDir::new().unwrap();
-    dir.child("not_file/is_file").touch().unwrap();
+    dir.child("dir/a.txt").write_str("aaaaaaaaaaaaaaaaaaaaaaaa").unwrap();
+    dir.child("dir/b/c.txt").write_str("Hello World!").unwrap();
     iroga_cmd()
-        .arg("unpack")
-        .arg(dir.path().join("not_file"))
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("dir")
+        .arg("--compression")
+        .arg("lzma")
         .assert()
-        .failure()
-        .code(1);
-    assert!(dir.child("not_file").is_dir());
+        .success();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("convert")
+        .arg("--to-version")
+        .arg("0x10001")
+        .arg("dir.iro")
+        .arg("converted.iro")
+        .assert()
+        .success()
+        .code(0);
+
+    let original = IroArchive::open(std::fs::File::open(dir.child("dir.iro").path()).unwrap())
+        .unwrap();
+    let mut converted =
+        IroArchive::open(std::fs::File::open(dir.child("converted.iro").path()).unwrap())
+            .unwrap();
+    assert_eq!(converted.header().version.to_string(), "0x10001");
+    assert_eq!(converted.entries().len(), original.entries().len());
+    for (original_entry, converted_entry) in original.entries().iter().zip(converted.entries()) {
+        assert_eq!(converted_entry.path, original_entry.path);
+        assert_eq!(converted_entry.flags, original_entry.flags);
+        assert_eq!(converted_entry.data_len, original_entry.data_len);
+    }
+    let entry = converted.by_path("b/c.txt").unwrap().clone();
+    let mut content = String::new();
+    converted
+        .open_entry(&entry)
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "Hello World!");
+    dir.close().unwrap();
 }
 
 #[test]
-pub fn unpack_output_path_already_exists() {
+pub fn convert_output_file_already_exists() {
     let dir = assert_fs::TempDir::new().unwrap();
     dir.child("dir/file.txt").touch().unwrap();
-    dir.child("dir.iro").touch().unwrap();
+    dir.child("converted.iro").touch().unwrap();
     iroga_cmd()
         .current_dir(dir.path())
-        .arg("unpack")
+        .arg("pack")
+        .arg("dir")
+        .assert()
+        .success();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("convert")
+        .arg("--to-version")
+        .arg("0x10000")
         .arg("dir.iro")
+        .arg("converted.iro")
         .assert()
         .failure()
         .code(1)
         .stderr(predicates::str::contains("output path already exists"));
+    dir.close().unwrap();
 }
 
 #[test]
-pub fn unpack_single_file() {
-    let iro_bytes: &[u8] = &hex!(
-        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
-        "01 00 00 00 24 00 10 00   66 00 69 00 6c 00 65 00"
-        "2e 00 74 00 78 00 74 00   00 00 00 00 38 00 00 00"
-        "00 00 00 00 17 00 00 00   48 65 6c 6c 6f 20 57 6f"
-        "72 6c 64 21 0d 0a 0d 0a   48 69 21 0d 0a 0d 0a   "
-    );
+pub fn patch_create_from_dirs() {
     let dir = assert_fs::TempDir::new().unwrap();
-    dir.child("single.iro")
-        .write_binary(iro_bytes)
-        .unwrap();
+    dir.child("base/same.txt").write_str("same").unwrap();
+    dir.child("base/changed.txt").write_str("before").unwrap();
+    dir.child("base/removed.txt").write_str("removed").unwrap();
+    dir.child("target/same.txt").write_str("same").unwrap();
+    dir.child("target/changed.txt").write_str("after").unwrap();
+    dir.child("target/sub/added.txt").write_str("added").unwrap();
 
     iroga_cmd()
         .current_dir(dir.path())
-        .arg("unpack")
-        .arg(dir.path().join("single.iro"))
+        .arg("patch")
+        .arg("create")
+        .arg("--base")
+        .arg("base")
+        .arg("--target")
+        .arg("target")
+        .arg("--output")
+        .arg("update.iro")
         .assert()
         .success()
-        .code(0);
+        .code(0)
+        .stdout(predicates::str::contains("\"sub/added.txt\" file added"))
+        .stdout(predicates::str::contains("\"changed.txt\" file changed"))
+        .stdout(predicates::str::contains("same.txt").not());
 
-    assert!(dir.child("single/file.txt").exists());
-    dir.child("single/file.txt").assert("Hello World!\r\n\r\nHi!\r\n\r\n");
+    let mut iro_archive =
+        IroArchive::open(std::fs::File::open(dir.child("update.iro").path()).unwrap()).unwrap();
+    assert_eq!(iro_archive.header().flags, IroFlags::Patch);
+    assert_eq!(iro_archive.entries().len(), 2);
+    assert!(iro_archive.by_path("same.txt").is_none());
+    let entry = iro_archive.by_path("sub/added.txt").unwrap().clone();
+    let mut content = String::new();
+    iro_archive
+        .open_entry(&entry)
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "added");
     dir.close().unwrap();
 }
 
 #[test]
-pub fn unpack_multiple_files() {
-    let iro_bytes: &[u8] = &hex!(
-        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
-        "03 00 00 00 1e 00 0a 00   61 00 2e 00 74 00 78 00"
-        "74 00 00 00 00 00 76 00   00 00 00 00 00 00 01 00"
-        "00 00 1e 00 0a 00 62 00   2e 00 74 00 78 00 74 00"
-        "00 00 00 00 77 00 00 00   00 00 00 00 01 00 00 00"
-        "26 00 12 00 64 00 69 00   72 00 5c 00 63 00 2e 00"
-        "74 00 78 00 74 00 00 00   00 00 78 00 00 00 00 00"
-        "00 00 01 00 00 00 41 42   43                     "
+pub fn patch_create_from_archives() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("base/same.txt").write_str("same content").unwrap();
+    dir.child("base/changed.txt").write_str("before").unwrap();
+    dir.child("target/same.txt").write_str("same content").unwrap();
+    dir.child("target/changed.txt").write_str("after").unwrap();
+    // the base is compressed differently, the content is compared decompressed
+    for (name, compression) in [("base", "lzss"), ("target", "lzma")] {
+        iroga_cmd()
+            .current_dir(dir.path())
+            .arg("pack")
+            .arg(name)
+            .arg("--compression")
+            .arg(compression)
+            .assert()
+            .success();
+    }
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("patch")
+        .arg("create")
+        .arg("--base")
+        .arg("base.iro")
+        .arg("--target")
+        .arg("target.iro")
+        .assert()
+        .success()
+        .code(0)
+        .stdout(predicates::str::contains("patch \"target-patch.iro\" has been created!"));
+
+    let target_bytes = std::fs::read(dir.child("target.iro").path()).unwrap();
+    let target = IroArchive::from_bytes(&target_bytes).unwrap();
+    let patch_bytes = std::fs::read(dir.child("target-patch.iro").path()).unwrap();
+    let patch = IroArchive::from_bytes(&patch_bytes).unwrap();
+    assert_eq!(patch.header().flags, IroFlags::Patch);
+    assert_eq!(patch.entries().len(), 1);
+    let entry = patch.by_path("changed.txt").unwrap();
+    let target_entry = target.by_path("changed.txt").unwrap();
+    assert_eq!(entry.flags, FileFlags::LzmaCompressed);
+    assert_eq!(
+        patch.raw_entry_bytes(entry).unwrap(),
+        target.raw_entry_bytes(target_entry).unwrap()
     );
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn patch_apply() {
     let dir = assert_fs::TempDir::new().unwrap();
-    dir.child("multiple.iro")
-        .write_binary(iro_bytes)
+    dir.child("base/a.txt").write_str("unchanged").unwrap();
+    dir.child("base/sub/b.txt").write_str("old b").unwrap();
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("base")
+        .arg("--compression")
+        .arg("lzma")
+        .assert()
+        .success();
+    let mut iro_writer = IroWriter::new(Cursor::new(Vec::new()));
+    iro_writer.set_flags(IroFlags::Patch);
+    iro_writer
+        .add_bytes("SUB/B.TXT", b"new b", Compression::Lzss)
+        .unwrap();
+    iro_writer
+        .add_bytes("c.txt", b"added c", Compression::None)
+        .unwrap();
+    let (patch_bytes, _, _) = iro_writer.finish().unwrap();
+    dir.child("update.iro")
+        .write_binary(patch_bytes.get_ref())
         .unwrap();
 
     iroga_cmd()
         .current_dir(dir.path())
-        .arg("unpack")
-        .arg(dir.path().join("multiple.iro"))
+        .arg("patch")
+        .arg("apply")
+        .arg("base.iro")
+        .arg("update.iro")
+        .arg("--output")
+        .arg("merged.iro")
+        .assert()
+        .success()
+        .code(0)
+        .stdout(predicates::str::contains("\"sub/b.txt\" file replaced"))
+        .stdout(predicates::str::contains("\"c.txt\" file added"));
+
+    let base_bytes = std::fs::read(dir.child("base.iro").path()).unwrap();
+    let base = IroArchive::from_bytes(&base_bytes).unwrap();
+    let merged_bytes = std::fs::read(dir.child("merged.iro").path()).unwrap();
+    let merged = IroArchive::from_bytes(&merged_bytes).unwrap();
+    assert_eq!(merged.header().flags, IroFlags::None);
+    let paths: Vec<&[u8]> = merged
+        .entries()
+        .iter()
+        .map(|entry| entry.path.as_slice())
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            "a.txt".encode_utf16().flat_map(u16::to_le_bytes).collect::<Vec<_>>(),
+            "sub\\b.txt".encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            "c.txt".encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        ]
+    );
+    let unchanged = merged.by_path("a.txt").unwrap();
+    assert_eq!(
+        merged.raw_entry_bytes(unchanged).unwrap(),
+        base.raw_entry_bytes(base.by_path("a.txt").unwrap()).unwrap()
+    );
+    let replaced = merged.by_path("sub/b.txt").unwrap();
+    assert_eq!(replaced.flags, FileFlags::LzssCompressed);
+    assert_eq!(merged.entry_bytes(replaced).unwrap(), Cow::Borrowed(b"new b"));
+    let added = merged.by_path("c.txt").unwrap();
+    assert_eq!(merged.entry_bytes(added).unwrap(), Cow::Borrowed(b"added c"));
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn patch_apply_duplicate_patch_entries() {
+    let mut iro_writer = IroWriter::new(Cursor::new(Vec::new()));
+    iro_writer
+        .add_bytes("a.txt", b"base a", Compression::None)
+        .unwrap();
+    let (base_bytes, _, _) = iro_writer.finish().unwrap();
+    let mut iro_writer = IroWriter::new(Cursor::new(Vec::new()));
+    iro_writer.set_flags(IroFlags::Patch);
+    let patch_files = [
+        ("a.txt", "first a"),
+        ("A.TXT", "second a"),
+        ("b.txt", "first b"),
+        ("b.txt", "second b"),
+    ];
+    for (path, data) in patch_files {
+        iro_writer
+            .add_bytes(path, data.as_bytes(), Compression::None)
+            .unwrap();
+    }
+    let (patch_bytes, _, _) = iro_writer.finish().unwrap();
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("base.iro").write_binary(base_bytes.get_ref()).unwrap();
+    dir.child("update.iro").write_binary(patch_bytes.get_ref()).unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("patch")
+        .arg("apply")
+        .arg("base.iro")
+        .arg("update.iro")
+        .arg("--output")
+        .arg("merged.iro")
         .assert()
         .success()
         .code(0);
 
-    dir.child("multiple/a.txt").assert("A");
-    dir.child("multiple/b.txt").assert("B");
-    dir.child("multiple/dir/c.txt").assert("C");
+    // the same patch entries returned by the lookup of the patch archive are applied
+    let patch = IroArchive::from_bytes(patch_bytes.get_ref()).unwrap();
+    let merged_bytes = std::fs::read(dir.child("merged.iro").path()).unwrap();
+    let merged = IroArchive::from_bytes(&merged_bytes).unwrap();
+    assert_eq!(merged.entries().len(), 2);
+    for path in ["a.txt", "b.txt"] {
+        let patch_entry = patch.by_path(path).unwrap();
+        let merged_entry = merged.by_path(path).unwrap();
+        assert_eq!(
+            merged.entry_bytes(merged_entry).unwrap(),
+            patch.entry_bytes(patch_entry).unwrap()
+        );
+    }
+    let replaced = merged.by_path("a.txt").unwrap();
+    assert_eq!(merged.entry_bytes(replaced).unwrap(), Cow::Borrowed(b"first a"));
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn patch_apply_not_patch() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("base/a.txt").write_str("a").unwrap();
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("base")
+        .assert()
+        .success();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("patch")
+        .arg("apply")
+        .arg("base.iro")
+        .arg("base.iro")
+        .arg("--output")
+        .arg("merged.iro")
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicates::str::contains("archive is not a patch"));
+    dir.child("merged.iro").assert(predicate::path::missing());
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn list_archive() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("dir/a.txt").write_str("aaa").unwrap();
+    dir.child("dir/b/c.txt").write_str("Hello World!").unwrap();
+    dir.child("dir/b/d.png").write_str("png").unwrap();
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("dir")
+        .arg("--compress-rule")
+        .arg("*.txt=lzss")
+        .assert()
+        .success();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("list")
+        .arg("dir.iro")
+        .assert()
+        .success()
+        .code(0)
+        .stdout("a.txt\nb/c.txt\nb/d.png\n");
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("list")
+        .arg("dir.iro")
+        .arg("--long")
+        .arg("--exclude")
+        .arg("b/*.txt")
+        .assert()
+        .success()
+        .code(0)
+        .stdout(concat!(
+            "lzss                  118          4 a.txt\n",
+            "uncompressed          136          3 b/d.png\n",
+        ));
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn list_archive_from_stdin() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("dir/a.txt").write_str("aaa").unwrap();
+    dir.child("dir/b/c.txt").write_str("Hello World!").unwrap();
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("dir")
+        .assert()
+        .success();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("list")
+        .arg("-")
+        .arg("--include")
+        .arg("b/*")
+        .write_stdin(std::fs::read(dir.child("dir.iro").path()).unwrap())
+        .assert()
+        .success()
+        .code(0)
+        .stdout("b/c.txt\n");
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn info_archive() {
+    const IRO_BYTES: &[u8] = &hex!(
+        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
+        "01 00 00 00 24 00 10 00   66 00 69 00 6c 00 65 00"
+        "2e 00 74 00 78 00 74 00   00 00 00 00 38 00 00 00"
+        "00 00 00 00 17 00 00 00   48 65 6c 6c 6f 20 57 6f"
+        "72 6c 64 21 0d 0a 0d 0a   48 69 21 0d 0a 0d 0a   "
+        "ff ff ff                                         "
+    );
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("single.iro").write_binary(IRO_BYTES).unwrap();
+
+    for iro_path in ["single.iro", "-"] {
+        iroga_cmd()
+            .current_dir(dir.path())
+            .arg("info")
+            .arg(iro_path)
+            .write_stdin(IRO_BYTES)
+            .assert()
+            .success()
+            .code(0)
+            .stdout(predicates::str::contains("- version: 0x10002"))
+            .stdout(predicates::str::contains("- type: Full IRO"))
+            .stdout(predicates::str::contains("- number of files: 1"))
+            .stdout(predicates::str::contains("- index size: 36 bytes"))
+            .stdout(predicates::str::contains("- data size: 23 bytes"))
+            .stdout(predicates::str::contains("- uncompressed files: 1"))
+            .stdout(predicates::str::contains("- lzss files: 0"))
+            .stdout(predicates::str::contains("- trailing bytes: 3"));
+    }
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn info_archive_padded_entry() {
+    // the length of the entry counts 4 bytes of padding after its fields
+    const IRO_BYTES: &[u8] = &hex!(
+        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
+        "01 00 00 00 28 00 10 00   66 00 69 00 6c 00 65 00"
+        "2e 00 74 00 78 00 74 00   00 00 00 00 3c 00 00 00"
+        "00 00 00 00 17 00 00 00   00 00 00 00 48 65 6c 6c"
+        "6f 20 57 6f 72 6c 64 21   0d 0a 0d 0a 48 69 21 0d"
+        "0a 0d 0a                                         "
+    );
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("padded.iro").write_binary(IRO_BYTES).unwrap();
+
+    for iro_path in ["padded.iro", "-"] {
+        iroga_cmd()
+            .current_dir(dir.path())
+            .arg("info")
+            .arg(iro_path)
+            .write_stdin(IRO_BYTES)
+            .assert()
+            .success()
+            .code(0)
+            .stdout(predicates::str::contains("- index size: 40 bytes"))
+            .stdout(predicates::str::contains("- data size: 23 bytes"))
+            .stdout(predicates::str::contains("- trailing bytes: 0"));
+    }
+    dir.close().unwrap();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+pub fn json_output() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("single/file.txt")
+        .write_str("Hello World!\r\n\r\nHi!\r\n\r\n")
+        .unwrap();
+
+    let output = iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("single")
+        .arg("--format")
+        .arg("json")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({"result": {
+            "output_path": "single.iro",
+            "header": {"version": "0x10002", "flags": "full", "size": 16, "num_files": 1},
+            "entries": [{"path": "file.txt", "flags": "uncompressed", "offset": 56, "size": 23, "stored_size": 23}],
+        }})
+    );
+
+    let output = iroga_cmd()
+        .current_dir(dir.path())
+        .arg("list")
+        .arg("single.iro")
+        .arg("--format")
+        .arg("json")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({"result": {
+            "header": {"version": "0x10002", "flags": "full", "size": 16, "num_files": 1},
+            "entries": [{"path": "file.txt", "flags": "uncompressed", "offset": 56, "stored_size": 23}],
+        }})
+    );
+
+    let output = iroga_cmd()
+        .current_dir(dir.path())
+        .arg("info")
+        .arg("single.iro")
+        .arg("--format")
+        .arg("json")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["result"]["index_size"], 36);
+    assert_eq!(json["result"]["flags_counts"]["uncompressed"], 1);
+    assert_eq!(json["result"]["trailing_bytes"], 0);
+
+    let output = iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg("single.iro")
+        .arg("--output")
+        .arg("unpacked")
+        .arg("--format")
+        .arg("json")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["result"]["output_path"], "unpacked");
+    assert_eq!(json["result"]["entries"][0]["path"], "file.txt");
+    dir.close().unwrap();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+pub fn json_output_patch_and_convert() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("base/a.txt").write_str("a").unwrap();
+    dir.child("target/a.txt").write_str("A").unwrap();
+    dir.child("target/b.txt").write_str("b").unwrap();
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("base")
+        .assert()
+        .success();
+
+    let output = iroga_cmd()
+        .current_dir(dir.path())
+        .arg("patch")
+        .arg("create")
+        .arg("--base")
+        .arg("base")
+        .arg("--target")
+        .arg("target")
+        .arg("--output")
+        .arg("update.iro")
+        .arg("--format")
+        .arg("json")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({"result": {
+            "output_path": "update.iro",
+            "header": {"version": "0x10002", "flags": "patch", "size": 16, "num_files": 2},
+            "added": ["b.txt"],
+            "changed": ["a.txt"],
+        }})
+    );
+
+    let output = iroga_cmd()
+        .current_dir(dir.path())
+        .arg("patch")
+        .arg("apply")
+        .arg("base.iro")
+        .arg("update.iro")
+        .arg("--output")
+        .arg("merged.iro")
+        .arg("--format")
+        .arg("json")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({"result": {
+            "output_path": "merged.iro",
+            "header": {"version": "0x10002", "flags": "full", "size": 16, "num_files": 2},
+            "added": ["b.txt"],
+            "changed": ["a.txt"],
+        }})
+    );
+
+    // stdout holds the merged archive itself, so the JSON goes to stderr
+    let output = iroga_cmd()
+        .current_dir(dir.path())
+        .arg("patch")
+        .arg("apply")
+        .arg("base.iro")
+        .arg("update.iro")
+        .arg("--output")
+        .arg("-")
+        .arg("--format")
+        .arg("json")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, std::fs::read(dir.child("merged.iro").path()).unwrap());
+    let json: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(json["result"]["output_path"], "-");
+
+    let output = iroga_cmd()
+        .current_dir(dir.path())
+        .arg("convert")
+        .arg("merged.iro")
+        .arg("merged-v0.iro")
+        .arg("--to-version")
+        .arg("0x10000")
+        .arg("--format")
+        .arg("json")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({"result": {
+            "output_path": "merged-v0.iro",
+            "from_version": "0x10002",
+            "header": {"version": "0x10000", "flags": "full", "size": 16, "num_files": 2},
+        }})
+    );
+    dir.close().unwrap();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+pub fn json_output_error() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("dir/file.txt").touch().unwrap();
+    dir.child("dir.iro").touch().unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("dir")
+        .arg("--format")
+        .arg("json")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("{\"error\":\"output path already exists: dir.iro\"}\n");
+    dir.close().unwrap();
+}
+
+#[cfg(not(feature = "serde"))]
+#[test]
+pub fn json_output_without_serde() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("dir/file.txt").touch().unwrap();
+
+    // the format argument only exists with the serde feature
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("dir")
+        .arg("--format")
+        .arg("json")
+        .assert()
+        .failure()
+        .code(2)
+        .stderr(predicates::str::contains("unexpected argument '--format'"));
+    assert!(!dir.child("dir.iro").exists());
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn pack_to_stdout() {
+    const EXPECTED_BYTES: &[u8] = &hex!(
+        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
+        "01 00 00 00 24 00 10 00   66 00 69 00 6c 00 65 00"
+        "2e 00 74 00 78 00 74 00   00 00 00 00 38 00 00 00"
+        "00 00 00 00 17 00 00 00   48 65 6c 6c 6f 20 57 6f"
+        "72 6c 64 21 0d 0a 0d 0a   48 69 21 0d 0a 0d 0a   "
+    );
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("single/file.txt")
+        .write_str("Hello World!\r\n\r\nHi!\r\n\r\n")
+        .unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("--output")
+        .arg("-")
+        .arg(dir.path().join("single"))
+        .assert()
+        .success()
+        .code(0)
+        .stdout(EXPECTED_BYTES)
+        .stderr(predicates::str::contains("\"file.txt\" file packed"));
+
+    dir.child("-").assert(predicate::path::missing());
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn pack_multiple_files() {
+    const EXPECTED_BYTES: &[u8] = &hex!(
+        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
+        "03 00 00 00 1e 00 0a 00   61 00 2e 00 74 00 78 00"
+        "74 00 00 00 00 00 76 00   00 00 00 00 00 00 01 00"
+        "00 00 1e 00 0a 00 62 00   2e 00 74 00 78 00 74 00"
+        "00 00 00 00 77 00 00 00   00 00 00 00 01 00 00 00"
+        "26 00 12 00 64 00 69 00   72 00 5c 00 63 00 2e 00"
+        "74 00 78 00 74 00 00 00   00 00 78 00 00 00 00 00"
+        "00 00 01 00 00 00 41 42   43                     "
+    );
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("multiple/a.txt").write_str("A").unwrap();
+    dir.child("multiple/b.txt").write_str("B").unwrap();
+    dir.child("multiple/dir/c.txt").write_str("C").unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg(dir.path().join("multiple"))
+        .assert()
+        .success()
+        .code(0);
+
+    assert!(dir.child("multiple.iro").exists());
+    dir.child("multiple.iro").assert(EXPECTED_BYTES);
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn pack_empty_dir() {
+    const EXPECTED_BYTES: &[u8] = &hex!(
+        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
+        "00 00 00 00                                      "
+    );
+    let dir = assert_fs::TempDir::new().unwrap();
+    std::fs::create_dir(dir.child("empty")).unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg(dir.path().join("empty"))
+        .assert()
+        .success()
+        .code(0);
+
+    dir.child("empty.iro").assert(EXPECTED_BYTES);
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn pack_specific_files() {
+    const EXPECTED_BYTES: &[u8] = &hex!(
+        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
+        "02 00 00 00 1e 00 0a 00   62 00 2e 00 74 00 78 00"
+        "74 00 00 00 00 00 58 00   00 00 00 00 00 00 01 00"
+        "00 00 26 00 12 00 64 00   69 00 72 00 5c 00 63 00"
+        "2e 00 74 00 78 00 74 00   00 00 00 00 59 00 00 00"
+        "00 00 00 00 01 00 00 00   42 43                  "
+    );
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("specific/a.txt").write_str("A").unwrap();
+    dir.child("specific/b.txt").write_str("B").unwrap();
+    dir.child("specific/dir/c.txt").write_str("C").unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("--include")
+        .arg("b.txt")
+        .arg("--include")
+        .arg("*/c.txt")
+        .arg(dir.path().join("specific"))
+        .assert()
+        .success()
+        .code(0);
+
+    assert!(dir.child("specific.iro").exists());
+    dir.child("specific.iro").assert(EXPECTED_BYTES);
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn pack_lzss_compressed() {
+    const EXPECTED_BYTES: &[u8] = &hex!(
+        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
+        "01 00 00 00 24 00 10 00   66 00 69 00 6c 00 65 00"
+        "2e 00 74 00 78 00 74 00   01 00 00 00 38 00 00 00"
+        "00 00 00 00 2D 00 00 00   FF 3C 3F 78 6D 6C 20 76"
+        "65 FF 72 73 69 6F 6E 3D   22 31 FF 2E 30 22 20 65"
+        "6E 63 6F FF 64 69 6E 67   3D 22 75 74 FF 66 2D 38"
+        "22 3F 3E 0D 0A                                   "
+    );
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("lzss/file.txt")
+        .write_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\r\n")
+        .unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("--compression")
+        .arg("lzss")
+        .arg(dir.path().join("lzss"))
+        .assert()
+        .success()
+        .code(0);
+
+    dir.child("lzss.iro").assert(EXPECTED_BYTES);
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn pack_and_unpack_lzss_compressed() {
+    let content = "Hello World!\r\n\r\nHi!\r\n\r\n".repeat(64);
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("lzss/file.txt").write_str(&content).unwrap();
+    dir.child("lzss/dir/empty.txt").touch().unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("--compression")
+        .arg("lzss")
+        .arg("lzss")
+        .arg("--output")
+        .arg("packed.iro")
+        .assert()
+        .success()
+        .code(0);
+    assert!(std::fs::metadata(dir.child("packed.iro")).unwrap().len() < content.len() as u64);
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg("packed.iro")
+        .arg("--output")
+        .arg("unpacked")
+        .assert()
+        .success()
+        .code(0);
+
+    dir.child("unpacked/file.txt").assert(content.as_str());
+    dir.child("unpacked/dir/empty.txt").assert("");
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn pack_and_unpack_lzma_compressed() {
+    let content = "Hello World!\r\n\r\nHi!\r\n\r\n".repeat(64);
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("lzma/file.txt").write_str(&content).unwrap();
+    dir.child("lzma/dir/empty.txt").touch().unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("--compression")
+        .arg("lzma")
+        .arg("lzma")
+        .assert()
+        .success()
+        .code(0);
+    assert!(std::fs::metadata(dir.child("lzma.iro")).unwrap().len() < content.len() as u64);
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg("lzma.iro")
+        .arg("--output")
+        .arg("unpacked")
+        .assert()
+        .success()
+        .code(0);
+
+    dir.child("unpacked/file.txt").assert(content.as_str());
+    dir.child("unpacked/dir/empty.txt").assert("");
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn unpack_invalid_lzma_decompressed_size() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("lzma/file.txt").write_str("Hello World!").unwrap();
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("--compression")
+        .arg("lzma")
+        .arg("lzma")
+        .assert()
+        .success();
+
+    // an unknown decompressed size (-1) is rejected whether the archive is a file or stdin
+    let mut iro_bytes = std::fs::read(dir.child("lzma.iro")).unwrap();
+    iro_bytes[0x38..0x3c].copy_from_slice(&hex!("ff ff ff ff"));
+    dir.child("corrupt.iro").write_binary(&iro_bytes).unwrap();
+    let expected_error = "[iroga error]: invalid decompressed size -1 of an LZMA entry\n";
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg("corrupt.iro")
+        .arg("--output")
+        .arg("from_file")
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(expected_error);
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg("-")
+        .arg("--output")
+        .arg("from_stdin")
+        .write_stdin(iro_bytes)
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(expected_error);
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn pack_lzma_preset() {
+    let content = "Hello World!\r\n\r\nHi!\r\n\r\n".repeat(2);
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("preset/file.txt").write_str(&content).unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("--compression")
+        .arg("lzma")
+        .arg("--lzma-level")
+        .arg("9")
+        .arg("--lzma-dict-size")
+        .arg("65536")
+        .arg("preset")
+        .assert()
+        .success()
+        .code(0);
+
+    // decompressed size, properties size, lc/lp/pb properties and dictionary size
+    let iro_bytes = std::fs::read(dir.child("preset.iro")).unwrap();
+    assert_eq!(&iro_bytes[0x28..0x2c], &hex!("02 00 00 00"));
+    assert_eq!(&iro_bytes[0x38..0x45], &hex!("2e 00 00 00 05 00 00 00 5d 00 00 01 00"));
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg("preset.iro")
+        .arg("--output")
+        .arg("unpacked")
+        .assert()
+        .success()
+        .code(0);
+    dir.child("unpacked/file.txt").assert(content.as_str());
+    dir.close().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+pub fn pack_failure_removes_output() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("dir/a.txt").write_str("a").unwrap();
+    // the dangling file can only be read once the archive has been created
+    std::os::unix::fs::symlink(dir.path().join("missing.txt"), dir.path().join("dir/b.txt"))
+        .unwrap();
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("dir")
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::starts_with("[iroga error]: No such file or directory"));
+    dir.child("dir.iro").assert(predicate::path::missing());
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn pack_invalid_lzma_level() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("dir/file.txt").touch().unwrap();
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("--compression")
+        .arg("lzma")
+        .arg("--lzma-level")
+        .arg("10")
+        .arg("dir")
+        .assert()
+        .failure();
+    dir.child("dir.iro").assert(predicate::path::missing());
+}
+
+#[test]
+pub fn pack_and_unpack_lzma2_compressed() {
+    let content = "Hello World!\r\n\r\nHi!\r\n\r\n".repeat(64);
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("lzma2/file.txt").write_str(&content).unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("--compression")
+        .arg("lzma2")
+        .arg("--lzma-dict-size")
+        .arg("65536")
+        .arg("lzma2")
+        .assert()
+        .success()
+        .code(0);
+
+    // decompressed size and properties size, LZMA2 chunks follow directly
+    let iro_bytes = std::fs::read(dir.child("lzma2.iro")).unwrap();
+    assert_eq!(&iro_bytes[0x28..0x2c], &hex!("02 00 00 00"));
+    assert_eq!(&iro_bytes[0x38..0x40], &hex!("c0 05 00 00 00 00 00 00"));
+    assert!(iro_bytes.len() < content.len());
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg("lzma2.iro")
+        .arg("--output")
+        .arg("unpacked")
+        .assert()
+        .success()
+        .code(0);
+
+    dir.child("unpacked/file.txt").assert(content.as_str());
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn pack_compression_rules() {
+    let content = "Hello World!\r\n\r\nHi!\r\n\r\n".repeat(16);
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("rules/a.ogg").write_str(&content).unwrap();
+    dir.child("rules/b.p").write_str(&content).unwrap();
+    dir.child("rules/dir/c.txt").write_str(&content).unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("--compression")
+        .arg("lzma")
+        .arg("--compress-rule")
+        .arg("*.ogg=none")
+        .arg("--compress-rule")
+        .arg("*.p=lzss")
+        .arg("rules")
+        .assert()
+        .success()
+        .code(0);
+
+    let iro_bytes = std::fs::read(dir.child("rules.iro")).unwrap();
+    assert_eq!(&iro_bytes[34..38], &hex!("00 00 00 00"));
+    assert_eq!(&iro_bytes[60..64], &hex!("01 00 00 00"));
+    assert_eq!(&iro_bytes[98..102], &hex!("02 00 00 00"));
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg("rules.iro")
+        .arg("--output")
+        .arg("unpacked")
+        .assert()
+        .success()
+        .code(0);
+
+    dir.child("unpacked/a.ogg").assert(content.as_str());
+    dir.child("unpacked/b.p").assert(content.as_str());
+    dir.child("unpacked/dir/c.txt").assert(content.as_str());
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn pack_invalid_compression_rule() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("dir/file.txt").touch().unwrap();
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("--compress-rule")
+        .arg("*.txt")
+        .arg("dir")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("invalid compression rule *.txt"));
+    dir.child("dir.iro").assert(predicate::path::missing());
+}
+
+#[test]
+pub fn pack_auto_compression() {
+    let content = "Hello World!\r\n\r\nHi!\r\n\r\n".repeat(64);
+    let mut seed = 0x2545f491u32;
+    let noise: Vec<u8> = (0..4096)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as u8
+        })
+        .collect();
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("auto/noise.bin").write_binary(&noise).unwrap();
+    dir.child("auto/text.txt").write_str(&content).unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("--compression")
+        .arg("auto")
+        .arg("auto")
+        .assert()
+        .success()
+        .code(0)
+        .stdout(predicates::str::contains("\"noise.bin\" file packed (uncompressed, 4096 -> 4096 bytes)"))
+        .stdout(predicates::str::contains("\"text.txt\" file packed (lzma, 1472 -> "));
+
+    let iro_bytes = std::fs::read(dir.child("auto.iro")).unwrap();
+    assert_eq!(&iro_bytes[42..46], &hex!("00 00 00 00"));
+    assert_eq!(&iro_bytes[78..82], &hex!("02 00 00 00"));
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg("auto.iro")
+        .arg("--output")
+        .arg("unpacked")
+        .assert()
+        .success()
+        .code(0);
+
+    assert_eq!(std::fs::read(dir.child("unpacked/noise.bin")).unwrap(), noise);
+    dir.child("unpacked/text.txt").assert(content.as_str());
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn pack_multiple_threads() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    for i in 0..64 {
+        dir.child(format!("threads/dir{}/file{}.txt", i % 4, i))
+            .write_str(&format!("File number {}\r\n", i).repeat(i + 1))
+            .unwrap();
+    }
+
+    for threads in ["1", "4"] {
+        iroga_cmd()
+            .current_dir(dir.path())
+            .arg("pack")
+            .arg("--compression")
+            .arg("auto")
+            .arg("--threads")
+            .arg(threads)
+            .arg("--output")
+            .arg(format!("threads_{}.iro", threads))
+            .arg("threads")
+            .assert()
+            .success()
+            .code(0);
+    }
+
+    let single_thread_bytes = std::fs::read(dir.child("threads_1.iro")).unwrap();
+    let multi_thread_bytes = std::fs::read(dir.child("threads_4.iro")).unwrap();
+    assert_eq!(single_thread_bytes, multi_thread_bytes);
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn pack_invalid_compression() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("dir/file.txt").touch().unwrap();
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("--compression")
+        .arg("zip")
+        .arg("dir")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("invalid compression zip"));
+    dir.child("dir.iro").assert(predicate::path::missing());
+}
+
+#[test]
+pub fn write_archive_in_memory() {
+    let text = "Hello World!\r\n\r\nHi!\r\n\r\n".repeat(16);
+    let mut iro_writer = IroWriter::new(Cursor::new(Vec::new()));
+    iro_writer
+        .add_bytes("a.txt", text.as_bytes(), Compression::None)
+        .unwrap();
+    iro_writer
+        .add_reader("dir/b.txt", text.as_bytes(), Compression::Lzss)
+        .unwrap();
+    iro_writer
+        .add_bytes("dir\\c.txt", text.as_bytes(), Compression::Lzma(LzmaPreset::default()))
+        .unwrap();
+    let (cursor, _, packed_entries) = iro_writer.finish().unwrap();
+
+    assert_eq!(packed_entries.len(), 3);
+    assert_eq!(packed_entries[1].path, "dir/b.txt");
+    assert_eq!(packed_entries[1].size, text.len() as u64);
+
+    let mut iro_archive = IroArchive::open(Cursor::new(cursor.into_inner())).unwrap();
+    let iro_entries = iro_archive.entries().to_vec();
+    let expected_paths = ["a.txt", "dir\\b.txt", "dir\\c.txt"];
+    for (iro_entry, expected_path) in iro_entries.iter().zip(expected_paths) {
+        let expected_path: Vec<u8> = expected_path
+            .encode_utf16()
+            .flat_map(|ch| ch.to_le_bytes())
+            .collect();
+        assert_eq!(iro_entry.path, expected_path);
+        let mut data = Vec::new();
+        iro_archive
+            .seek_and_read_file_entry(iro_entry, &mut data)
+            .unwrap();
+        assert_eq!(data, text.as_bytes());
+    }
+}
+
+#[test]
+pub fn open_archive_reads_rest_of_index_at_once() {
+    struct CountingReader {
+        inner: Cursor<Vec<u8>>,
+        reads: usize,
+    }
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.reads += 1;
+            self.inner.read(buf)
+        }
+    }
+    impl Seek for CountingReader {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    let mut iro_writer = IroWriter::new(Cursor::new(Vec::new()));
+    for i in 0..100 {
+        let path = format!("dir/{}.txt", "file".repeat(i % 8 + 1));
+        iro_writer
+            .add_bytes(&path, b"Hello World!", Compression::None)
+            .unwrap();
+    }
+    let (cursor, _, _) = iro_writer.finish().unwrap();
+
+    let mut counting_reader = CountingReader {
+        inner: cursor,
+        reads: 0,
+    };
+    counting_reader.seek(SeekFrom::Start(0)).unwrap();
+    let iro_archive = IroArchive::open(&mut counting_reader).unwrap();
+    assert_eq!(iro_archive.entries().len(), 100);
+    // header, then the first entries and the rest of the index up to the first offset
+    assert_eq!(counting_reader.reads, 3);
+}
+
+#[test]
+pub fn read_archive_entries_lazily() {
+    let text = "Hello World!\r\n\r\nHi!\r\n\r\n".repeat(16);
+    let mut iro_writer = IroWriter::new(Cursor::new(Vec::new()));
+    let compressions = [
+        Compression::None,
+        Compression::Lzss,
+        Compression::Lzma(LzmaPreset::default()),
+        Compression::Lzma2(LzmaPreset::default()),
+    ];
+    for (i, compression) in compressions.into_iter().enumerate() {
+        iro_writer
+            .add_bytes(&format!("{i}.txt"), text.as_bytes(), compression)
+            .unwrap();
+    }
+    let (cursor, _, _) = iro_writer.finish().unwrap();
+
+    let mut iro_archive = IroArchive::open(Cursor::new(cursor.into_inner())).unwrap();
+    let iro_entries = iro_archive.entries().to_vec();
+    for iro_entry in &iro_entries {
+        let mut entry_reader = iro_archive.open_entry(iro_entry).unwrap();
+        let mut data = String::new();
+        entry_reader.read_to_string(&mut data).unwrap();
+        assert_eq!(data, text);
+    }
+
+    let mut entry_reader = iro_archive.open_entry(&iro_entries[0]).unwrap();
+    assert!(entry_reader.is_seekable());
+    assert_eq!(entry_reader.seek(SeekFrom::End(-5)).unwrap(), text.len() as u64 - 5);
+    let mut data = String::new();
+    entry_reader.read_to_string(&mut data).unwrap();
+    assert_eq!(data, "!\r\n\r\n");
+    assert!(entry_reader.seek(SeekFrom::Current(1)).is_err());
+
+    let mut entry_reader = iro_archive.open_entry(&iro_entries[1]).unwrap();
+    assert!(!entry_reader.is_seekable());
+    assert!(entry_reader.seek(SeekFrom::Start(0)).is_err());
+}
+
+#[test]
+pub fn read_archive_entries_concurrently() {
+    let texts: Vec<String> = (0..8).map(|i| format!("file {i}\r\n").repeat(64)).collect();
+    let mut iro_writer = IroWriter::new(Cursor::new(Vec::new()));
+    for (i, text) in texts.iter().enumerate() {
+        iro_writer
+            .add_bytes(&format!("{i}.txt"), text.as_bytes(), Compression::Lzss)
+            .unwrap();
+    }
+    let (cursor, _, _) = iro_writer.finish().unwrap();
+
+    let iro_archive = IroArchive::open(Cursor::new(cursor.into_inner())).unwrap();
+    std::thread::scope(|scope| {
+        for (iro_entry, text) in iro_archive.entries().iter().zip(&texts) {
+            let iro_archive = &iro_archive;
+            scope.spawn(move || {
+                let mut data = String::new();
+                iro_archive
+                    .open_entry_at(iro_entry)
+                    .unwrap()
+                    .read_to_string(&mut data)
+                    .unwrap();
+                assert_eq!(&data, text);
+            });
+        }
+    });
+}
+
+#[test]
+pub fn read_archive_from_bytes() {
+    let text = "Hello World!\r\n\r\nHi!\r\n\r\n".repeat(16);
+    let mut iro_writer = IroWriter::new(Cursor::new(Vec::new()));
+    iro_writer
+        .add_bytes("a.txt", text.as_bytes(), Compression::None)
+        .unwrap();
+    iro_writer
+        .add_bytes("b.txt", text.as_bytes(), Compression::Lzma(LzmaPreset::default()))
+        .unwrap();
+    let iro_bytes = iro_writer.finish().unwrap().0.into_inner();
+
+    let iro_archive = IroArchive::from_bytes(&iro_bytes).unwrap();
+    let uncompressed = iro_archive.by_path("a.txt").unwrap();
+    let data = iro_archive.entry_bytes(uncompressed).unwrap();
+    assert!(matches!(data, Cow::Borrowed(_)));
+    assert_eq!(data.as_ref(), text.as_bytes());
+    assert!(iro_bytes.as_ptr_range().contains(&data.as_ptr()));
+
+    let compressed = iro_archive.by_path("b.txt").unwrap();
+    let data = iro_archive.entry_bytes(compressed).unwrap();
+    assert!(matches!(data, Cow::Owned(_)));
+    assert_eq!(data.as_ref(), text.as_bytes());
+    assert_eq!(
+        iro_archive.raw_entry_bytes(compressed).unwrap().len(),
+        compressed.data_len as usize
+    );
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+pub fn read_archive_from_mmap() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("mod/a.txt").write_str("A").unwrap();
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("pack")
+        .arg("mod")
+        .assert()
+        .success();
+
+    let iro_file = std::fs::File::open(dir.child("mod.iro").path()).unwrap();
+    let iro_archive = unsafe { IroArchive::open_mmap(&iro_file) }.unwrap();
+    let iro_entry = iro_archive.by_path("a.txt").unwrap();
+    assert_eq!(iro_archive.entry_bytes(iro_entry).unwrap().as_ref(), b"A");
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn iterate_borrowed_entries() {
+    let mut iro_writer = IroWriter::new(Cursor::new(Vec::new()));
+    iro_writer
+        .add_bytes("field/MD1STIN", b"md1stin", Compression::None)
+        .unwrap();
+    iro_writer
+        .add_bytes("field/md1_1", b"md1_1", Compression::Lzss)
+        .unwrap();
+    let iro_bytes = iro_writer.finish().unwrap().0.into_inner();
+
+    let iro_entry_refs = IroEntryRefs::new(&iro_bytes).unwrap();
+    assert_eq!(iro_entry_refs.header().num_files, 2);
+    let iro_entry_refs: Vec<IroEntryRef> = iro_entry_refs.map(Result::unwrap).collect();
+    assert_eq!(iro_entry_refs.len(), 2);
+    assert!(iro_entry_refs[0].path_eq("FIELD\\md1stin"));
+    assert!(!iro_entry_refs[0].path_eq("field/md1st"));
+    assert!(!iro_entry_refs[0].path_eq("field/md1stin2"));
+    assert!(iro_bytes.as_ptr_range().contains(&iro_entry_refs[1].path.as_ptr()));
+
+    let iro_archive = IroArchive::from_bytes(&iro_bytes).unwrap();
+    for (iro_entry_ref, iro_entry) in iro_archive
+        .entry_refs()
+        .unwrap()
+        .zip(iro_archive.entries())
+    {
+        let iro_entry_ref = iro_entry_ref.unwrap();
+        assert_eq!(iro_entry_ref.path, iro_entry.path.as_slice());
+        assert_eq!(iro_entry_ref.offset, iro_entry.offset);
+    }
+
+    // truncated index
+    let mut iro_entry_refs = IroEntryRefs::new(&iro_bytes[..76]).unwrap();
+    assert!(iro_entry_refs.next().unwrap().is_ok());
+    assert!(iro_entry_refs.next().unwrap().is_err());
+    assert!(iro_entry_refs.next().is_none());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+pub async fn write_and_read_archive_async() {
+    use iroga::iro_async::{AsyncIroArchive, AsyncIroWriter};
+
+    // larger than the chunks in which entries are copied and decompressed
+    let text = "Hello World!\r\n\r\nHi!\r\n\r\n".repeat(4096);
+    let mut iro_writer = AsyncIroWriter::new(Cursor::new(Vec::new()));
+    iro_writer.set_flags(IroFlags::Patch);
+    iro_writer
+        .add_bytes("a.txt", text.as_bytes(), Compression::None)
+        .unwrap();
+    iro_writer
+        .add_reader("dir/b.txt", text.as_bytes(), Compression::Lzss)
+        .unwrap();
+    iro_writer
+        .add_bytes("dir/c.txt", text.as_bytes(), Compression::Lzma(LzmaPreset::default()))
+        .unwrap();
+    iro_writer
+        .add_reader("dir/d.txt", text.as_bytes(), Compression::None)
+        .unwrap();
+    let (cursor, _, packed_entries) = iro_writer.finish().await.unwrap();
+    assert_eq!(packed_entries.len(), 4);
+
+    // same bytes of the blocking writer
+    let mut blocking_writer = IroWriter::new(Cursor::new(Vec::new()));
+    blocking_writer.set_flags(IroFlags::Patch);
+    blocking_writer
+        .add_bytes("a.txt", text.as_bytes(), Compression::None)
+        .unwrap();
+    blocking_writer
+        .add_bytes("dir/b.txt", text.as_bytes(), Compression::Lzss)
+        .unwrap();
+    blocking_writer
+        .add_bytes("dir/c.txt", text.as_bytes(), Compression::Lzma(LzmaPreset::default()))
+        .unwrap();
+    blocking_writer
+        .add_reader("dir/d.txt", text.as_bytes(), Compression::None)
+        .unwrap();
+    let (blocking_cursor, _, _) = blocking_writer.finish().unwrap();
+    assert_eq!(cursor.get_ref(), blocking_cursor.get_ref());
+
+    let mut iro_archive = AsyncIroArchive::open(Cursor::new(cursor.into_inner()))
+        .await
+        .unwrap();
+    assert_eq!(iro_archive.header().flags, IroFlags::Patch);
+    assert_eq!(iro_archive.entries().len(), 4);
+    for path in ["a.txt", "dir/b.txt", "DIR\\C.TXT", "dir/d.txt"] {
+        let iro_entry = iro_archive.by_path(path).unwrap().clone();
+        let mut data = Vec::new();
+        iro_archive.copy_entry(&iro_entry, &mut data).await.unwrap();
+        assert_eq!(data, text.as_bytes());
+    }
+}
+
+#[test]
+pub fn lookup_archive_entries() {
+    let mut iro_writer = IroWriter::new(Cursor::new(Vec::new()));
+    iro_writer
+        .add_bytes("field/MD1STIN", b"md1stin", Compression::None)
+        .unwrap();
+    iro_writer
+        .add_bytes("field\\md1_1", b"md1_1", Compression::Lzss)
+        .unwrap();
+    let (cursor, _, _) = iro_writer.finish().unwrap();
+
+    let mut iro_archive = IroArchive::open(Cursor::new(cursor.into_inner())).unwrap();
+    assert_eq!(iro_archive.header().num_files, 2);
+    assert_eq!(iro_archive.entries().len(), 2);
+    assert!(iro_archive.by_index(2).is_none());
+    assert!(iro_archive.by_path("field/md1_2").is_none());
+
+    for (path, expected_data) in [
+        ("field/md1stin", "md1stin"),
+        ("FIELD\\Md1StIn", "md1stin"),
+        ("field/md1_1", "md1_1"),
+    ] {
+        let iro_entry = iro_archive.by_path(path).unwrap().clone();
+        let mut data = String::new();
+        iro_archive
+            .open_entry(&iro_entry)
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+        assert_eq!(data, expected_data);
+    }
+    assert_eq!(
+        iro_archive.by_index(1).unwrap().offset,
+        iro_archive.by_path("field/md1_1").unwrap().offset
+    );
+}
+
+#[test]
+pub fn unpack_not_exists_file() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg(dir.path().join("not_exists_file.iro"))
+        .assert()
+        .failure()
+        .code(1);
+    assert!(!dir.child("not_exists_file.iro").exists());
+}
+
+#[test]
+pub fn unpack_not_file() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("not_file/is_file").touch().unwrap();
+    iroga_cmd()
+        .arg("unpack")
+        .arg(dir.path().join("not_file"))
+        .assert()
+        .failure()
+        .code(1);
+    assert!(dir.child("not_file").is_dir());
+}
+
+#[test]
+pub fn unpack_output_path_already_exists() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("dir/file.txt").touch().unwrap();
+    dir.child("dir.iro").touch().unwrap();
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg("dir.iro")
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicates::str::contains("output path already exists"));
+}
+
+#[test]
+pub fn unpack_single_file() {
+    let iro_bytes: &[u8] = &hex!(
+        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
+        "01 00 00 00 24 00 10 00   66 00 69 00 6c 00 65 00"
+        "2e 00 74 00 78 00 74 00   00 00 00 00 38 00 00 00"
+        "00 00 00 00 17 00 00 00   48 65 6c 6c 6f 20 57 6f"
+        "72 6c 64 21 0d 0a 0d 0a   48 69 21 0d 0a 0d 0a   "
+    );
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("single.iro")
+        .write_binary(iro_bytes)
+        .unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg(dir.path().join("single.iro"))
+        .assert()
+        .success()
+        .code(0);
+
+    assert!(dir.child("single/file.txt").exists());
+    dir.child("single/file.txt").assert("Hello World!\r\n\r\nHi!\r\n\r\n");
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn unpack_multiple_threads() {
+    let iro_bytes: &[u8] = &hex!(
+        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
+        "03 00 00 00 1e 00 0a 00   61 00 2e 00 74 00 78 00"
+        "74 00 00 00 00 00 76 00   00 00 00 00 00 00 01 00"
+        "00 00 1e 00 0a 00 62 00   2e 00 74 00 78 00 74 00"
+        "00 00 00 00 77 00 00 00   00 00 00 00 01 00 00 00"
+        "26 00 12 00 64 00 69 00   72 00 5c 00 63 00 2e 00"
+        "74 00 78 00 74 00 00 00   00 00 78 00 00 00 00 00"
+        "00 00 01 00 00 00 41 42   43                     "
+    );
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("multiple.iro")
+        .write_binary(iro_bytes)
+        .unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg(dir.path().join("multiple.iro"))
+        .arg("--threads")
+        .arg("3")
+        .assert()
+        .success()
+        .code(0)
+        .stdout(predicates::str::contains(
+            "\"a.txt\" file written!\n\"b.txt\" file written!\n\"dir/c.txt\" file written!",
+        ));
+
+    dir.child("multiple/a.txt").assert("A");
+    dir.child("multiple/b.txt").assert("B");
+    dir.child("multiple/dir/c.txt").assert("C");
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn unpack_duplicate_paths_multiple_threads() {
+    let contents: Vec<String> = (0..64).map(|i| format!("{i}").repeat(i + 1)).collect();
+    let mut iro_writer = IroWriter::new(Cursor::new(Vec::new()));
+    for (i, content) in contents.iter().enumerate() {
+        let path = if i % 2 == 0 { "dup.txt" } else { "Dup.txt" };
+        iro_writer
+            .add_bytes(path, content.as_bytes(), Compression::None)
+            .unwrap();
+    }
+    iro_writer
+        .add_bytes("dup.TXT", b"last", Compression::None)
+        .unwrap();
+    let (cursor, _, _) = iro_writer.finish().unwrap();
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("dup.iro")
+        .write_binary(&cursor.into_inner())
+        .unwrap();
+
+    // the last entry with the same path (compared case-insensitively) wins, as with one thread
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg("dup.iro")
+        .arg("--threads")
+        .arg("8")
+        .assert()
+        .success()
+        .code(0)
+        .stdout("\"dup.TXT\" file written!\nIRO unpacked into \"dup\" directory\n");
+
+    let entries: Vec<_> = std::fs::read_dir(dir.child("dup")).unwrap().collect();
+    assert_eq!(entries.len(), 1);
+    dir.child("dup/dup.TXT").assert("last");
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn unpack_invalid_entry_length() {
+    let iro_bytes: &[u8] = &hex!(
+        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
+        "01 00 00 00 00 00 10 00   66 00 69 00 6c 00 65 00"
+        "2e 00 74 00 78 00 74 00   00 00 00 00 38 00 00 00"
+    );
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("invalid.iro")
+        .write_binary(iro_bytes)
+        .unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg(dir.path().join("invalid.iro"))
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicates::str::contains("invalid entry length 0"));
+
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn unpack_truncated_index() {
+    let iro_bytes: &[u8] = &hex!(
+        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
+        "02 00 00 00 24 00 10 00   66 00 69 00 6c 00 65 00"
+        "2e 00 74 00 78 00 74 00   00 00 00 00 38 00 00 00"
+        "00 00 00 00 17 00 00 00   24 00                  "
+    );
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("truncated.iro")
+        .write_binary(iro_bytes)
+        .unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg(dir.path().join("truncated.iro"))
+        .assert()
+        .failure()
+        .code(1);
+
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn unpack_multiple_files() {
+    let iro_bytes: &[u8] = &hex!(
+        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
+        "03 00 00 00 1e 00 0a 00   61 00 2e 00 74 00 78 00"
+        "74 00 00 00 00 00 76 00   00 00 00 00 00 00 01 00"
+        "00 00 1e 00 0a 00 62 00   2e 00 74 00 78 00 74 00"
+        "00 00 00 00 77 00 00 00   00 00 00 00 01 00 00 00"
+        "26 00 12 00 64 00 69 00   72 00 5c 00 63 00 2e 00"
+        "74 00 78 00 74 00 00 00   00 00 78 00 00 00 00 00"
+        "00 00 01 00 00 00 41 42   43                     "
+    );
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("multiple.iro")
+        .write_binary(iro_bytes)
+        .unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg(dir.path().join("multiple.iro"))
+        .assert()
+        .success()
+        .code(0);
+
+    dir.child("multiple/a.txt").assert("A");
+    dir.child("multiple/b.txt").assert("B");
+    dir.child("multiple/dir/c.txt").assert("C");
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn unpack_from_stdin() {
+    // entries are stored in reverse order of their offsets
+    let iro_bytes: &[u8] = &hex!(
+        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
+        "03 00 00 00 1e 00 0a 00   61 00 2e 00 74 00 78 00"
+        "74 00 00 00 00 00 78 00   00 00 00 00 00 00 01 00"
+        "00 00 1e 00 0a 00 62 00   2e 00 74 00 78 00 74 00"
+        "00 00 00 00 77 00 00 00   00 00 00 00 01 00 00 00"
+        "26 00 12 00 64 00 69 00   72 00 5c 00 63 00 2e 00"
+        "74 00 78 00 74 00 00 00   00 00 76 00 00 00 00 00"
+        "00 00 01 00 00 00 43 42   41                     "
+    );
+    let dir = assert_fs::TempDir::new().unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg("-")
+        .arg("--output")
+        .arg("multiple")
+        .write_stdin(iro_bytes)
+        .assert()
+        .success()
+        .code(0);
+
+    dir.child("multiple/a.txt").assert("A");
+    dir.child("multiple/b.txt").assert("B");
+    dir.child("multiple/dir/c.txt").assert("C");
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn unpack_from_stdin_without_output() {
+    let dir = assert_fs::TempDir::new().unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg("-")
+        .write_stdin(&[] as &[u8])
+        .assert()
+        .failure()
+        .code(1);
+
+    dir.close().unwrap();
+}
+
+#[test]
+pub fn unpack_from_stdin_overlapping_entries() {
+    let iro_bytes: &[u8] = &hex!(
+        "49 52 4f 53 02 00 01 00   00 00 00 00 10 00 00 00"
+        "02 00 00 00 1e 00 0a 00   61 00 2e 00 74 00 78 00"
+        "74 00 00 00 00 00 50 00   00 00 00 00 00 00 02 00"
+        "00 00 1e 00 0a 00 62 00   2e 00 74 00 78 00 74 00"
+        "00 00 00 00 51 00 00 00   00 00 00 00 01 00 00 00"
+        "41 42                                            "
+    );
+    let dir = assert_fs::TempDir::new().unwrap();
+
+    iroga_cmd()
+        .current_dir(dir.path())
+        .arg("unpack")
+        .arg("-")
+        .arg("--output")
+        .arg("overlapping")
+        .write_stdin(iro_bytes)
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicates::str::contains("entry offset 81"));
+
     dir.close().unwrap();
 }
 